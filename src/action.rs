@@ -1,23 +1,52 @@
-use crate::modules::screenshot::state::Tool;
+use crate::modules::screenshot::state::{
+    Arrowhead, Contrast, Dash, Modifiers, Rect, SelectionHitZone, SelectionMode, SelectionPreset, Tool,
+};
 
 pub enum AppAction {
     Screenshot(ScreenshotAction),
-    Global(GlobalAction)
 }
 
 pub enum ScreenshotAction {
     SetTool(Tool),
     SetColor(u8, u8, u8),
+    SetStrokeWidth(u8),
+    SetDash(Dash),
+    SetOpacity(u8),
+    SetContrast(Contrast),
+    SetArrowhead(Arrowhead),
+    SetFillColor(u8, u8, u8),
+    SetFillOpacity(u8),
+    SetPixelBlock(i32),
+    SetSpotlightEllipse(bool),
+    SetSpotlightOpacity(u8),
+    SetMagnifyZoom(i32),
+    SetMagnifyConnector(bool),
     ToogleMode,
+    Cancel,
     MouseMove(i32, i32),
-    DragBegin(i32, i32),
-    DragUpdate(i32, i32),
+    DragBegin(i32, i32, Modifiers),
+    DragUpdate(i32, i32, Modifiers),
     DragEnd,
+    Nudge(i32, i32),
+    NudgeEdge(SelectionHitZone, i32, i32),
+    CycleHandle(bool),
+    SetPreset(SelectionPreset),
+    SetSelection(Rect),
+    OpenSelectionEditor,
+    SetSelectionMode(SelectionMode),
+    ClosePolygon,
+    TextInput(String),
+    TextBackspace,
+    TextDelete,
+    /// Moves the caret right, or left if `false`.
+    TextMoveCaret(bool),
+    TextCommit,
+    SetTextFont(String),
+    SetTextBackground(bool),
 
     Save,
     Undo,
-}
-
-pub enum GlobalAction {
-    Quit
+    Redo,
+    DeleteShape,
+    ResetMarkers,
 }
\ No newline at end of file
@@ -1,22 +1,201 @@
 use std::process::Command;
 
-use anyhow::Result;
-use gdk_pixbuf::{Pixbuf, PixbufLoader};
-use gtk::prelude::PixbufLoaderExt;
+use anyhow::{Context, Result};
 
-pub fn capture_fullscreen() -> Result<Pixbuf> {
-    
-    let output = Command::new("grim").arg("-").output()?;
+use crate::capture::screenshot::hygiene::CaptureHygiene;
+use crate::capture::screenshot::redact::redact_sensitive;
+use crate::common::tiled_surface::TiledSurface;
+use crate::common::timing::PhaseTimer;
+use crate::config::Config;
+use crate::modules::screenshot::state::Rect;
+
+pub fn capture_fullscreen(config: &Config, timer: &mut PhaseTimer) -> Result<TiledSurface> {
+
+    let ppm = {
+        let _hygiene = CaptureHygiene::apply(&config.hyprland);
+        timer.mark("hygiene");
+
+        grab_ppm(None)
+    }?;
+    timer.mark("grim");
+
+    let mut surface = ppm_to_surface(&ppm)?;
+    timer.mark("convert");
+
+    redact_sensitive(&mut surface, &config.redact)?;
+    timer.mark("redact");
+
+    Ok(surface)
+}
+
+/// Runs grim with uncompressed PPM output, skipping the PNG encode/decode roundtrip.
+/// Captures every output, or only `region` in layout coordinates.
+fn grab_ppm(region: Option<&Rect>) -> Result<Vec<u8>> {
+    let mut grim = Command::new("grim");
+
+    if let Some(r) = region {
+        grim.args(["-g", &format!("{},{} {}x{}", r.x, r.y, r.w, r.h)]);
+    }
+
+    let output = grim
+        .args(["-t", "ppm", "-"])
+        .output()
+        .context("Failed to spawn grim")?;
 
     anyhow::ensure!(output.status.success(), "grim returned non-zero status");
 
-    let loader = PixbufLoader::new();
-    loader.write(&output.stdout)?;
-    loader.close()?;
+    Ok(output.stdout)
+}
+
+/// Captures `region` and tells whether every pixel of it is black, which is
+/// how Hyprland draws layers hidden from screencopy.
+pub fn is_blacked_out(region: &Rect) -> Result<bool> {
+    let ppm = grab_ppm(Some(region))?;
+    let header = PpmHeader::parse(&ppm)?;
+    let pixels = ppm.get(header.data_offset..).unwrap_or_default();
+
+    Ok(!pixels.is_empty() && pixels.iter().all(|&c| c == 0))
+}
+
+/// Converts a binary (P6) PPM image straight into Cairo ARGB32 memory.
+pub fn ppm_to_surface(ppm: &[u8]) -> Result<TiledSurface> {
+
+    let header = PpmHeader::parse(ppm)?;
+    let (width, height) = (header.width, header.height);
+
+    let pixels = ppm.get(header.data_offset..).unwrap_or_default();
+    let row_len = width * 3;
+    anyhow::ensure!(pixels.len() >= row_len * height, "PPM pixel data is truncated");
+
+    let mut surface = TiledSurface::new(width as i32, height as i32)?;
+
+    for tile in surface.tiles_mut()? {
+        let (tile_x, tile_y) = (tile.x as usize, tile.y as usize);
+        let (tile_w, tile_h) = (tile.surface.width() as usize, tile.surface.height() as usize);
+        let stride = tile.surface.stride() as usize;
+
+        {
+            let mut data = tile.surface.data().context("Failed to access surface data")?;
+
+            for (row, dst_row) in data.chunks_exact_mut(stride).take(tile_h).enumerate() {
+                let offset = (tile_y + row) * row_len + tile_x * 3;
+                let src_row = &pixels[offset..offset + tile_w * 3];
+
+                for (rgb, argb) in src_row.chunks_exact(3).zip(dst_row.chunks_exact_mut(4)) {
+                    let pixel = 0xff00_0000
+                        | (rgb[0] as u32) << 16
+                        | (rgb[1] as u32) << 8
+                        | rgb[2] as u32;
+                    argb.copy_from_slice(&pixel.to_ne_bytes());
+                }
+            }
+        }
+
+        tile.surface.mark_dirty();
+    }
+
+    Ok(surface)
+}
+
+struct PpmHeader {
+    width: usize,
+    height: usize,
+    data_offset: usize,
+}
+
+impl PpmHeader {
+    fn parse(buf: &[u8]) -> Result<Self> {
+        anyhow::ensure!(buf.starts_with(b"P6"), "Unsupported image format, expected binary PPM");
+
+        let mut pos = 2;
+        let mut fields = [0usize; 3];
+
+        for field in fields.iter_mut() {
+            // Skip whitespace and comments
+            loop {
+                match buf.get(pos) {
+                    Some(b'#') => {
+                        while buf.get(pos).is_some_and(|&c| c != b'\n') { pos += 1; }
+                    }
+                    Some(c) if c.is_ascii_whitespace() => pos += 1,
+                    _ => break,
+                }
+            }
+
+            let start = pos;
+            while buf.get(pos).is_some_and(u8::is_ascii_digit) { pos += 1; }
+
+            *field = std::str::from_utf8(&buf[start..pos])?
+                .parse()
+                .context("Malformed PPM header")?;
+        }
+
+        let [width, height, max_value] = fields;
+        anyhow::ensure!(max_value == 255, "Unsupported PPM max value: {max_value}");
+
+        // Exactly one whitespace byte separates the header from the pixel data
+        Ok(Self { width, height, data_offset: pos + 1 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(surface: &TiledSurface, x: i32, y: i32) -> u32 {
+        let region = surface.region(&Rect { x, y, w: 1, h: 1 }).unwrap();
+        let mut pixel = 0;
+        region.with_data(|data| pixel = u32::from_ne_bytes([data[0], data[1], data[2], data[3]])).unwrap();
+        pixel
+    }
+
+    #[test]
+    fn header_skips_comments_and_whitespace() {
+        let header = PpmHeader::parse(b"P6\n# grim\n3  2\n255\n").unwrap();
+
+        assert_eq!((header.width, header.height), (3, 2));
+        assert_eq!(header.data_offset, 19);
+    }
+
+    #[test]
+    fn header_rejects_other_formats() {
+        assert!(PpmHeader::parse(b"P3\n1 1\n255\n").is_err());
+        assert!(PpmHeader::parse(b"P6\n1 1\n65535\n").is_err());
+        assert!(PpmHeader::parse(b"P6\n1\n").is_err());
+    }
+
+    #[test]
+    fn pixels_become_opaque_argb() {
+        let mut ppm = b"P6\n2 1\n255\n".to_vec();
+        ppm.extend_from_slice(&[0x12, 0x34, 0x56, 0xff, 0x00, 0x80]);
+
+        let surface = ppm_to_surface(&ppm).unwrap();
+
+        assert_eq!((surface.width(), surface.height()), (2, 1));
+        assert_eq!(pixel(&surface, 0, 0), 0xff12_3456);
+        assert_eq!(pixel(&surface, 1, 0), 0xffff_0080);
+    }
+
+    #[test]
+    fn rows_continue_across_tiles() {
+        let width: usize = 4100;
+        let mut ppm = format!("P6\n{width} 2\n255\n").into_bytes();
+        ppm.extend((0..width * 2).flat_map(|i| [(i % 251) as u8, (i / 251 % 256) as u8, 7]));
+
+        let surface = ppm_to_surface(&ppm).unwrap();
+
+        for i in [0, 4095, 4096, 4099, width + 4097] {
+            let (x, y) = ((i % width) as i32, (i / width) as i32);
+            let expected = 0xff00_0007 | ((i % 251) as u32) << 16 | ((i / 251 % 256) as u32) << 8;
+            assert_eq!(pixel(&surface, x, y), expected, "pixel {i}");
+        }
+    }
 
-    let pixbuf = loader
-        .pixbuf()
-        .ok_or_else(|| anyhow::anyhow!("Failed to load pixbuf"))?;
+    #[test]
+    fn truncated_pixels_are_rejected() {
+        let mut ppm = b"P6\n2 2\n255\n".to_vec();
+        ppm.extend_from_slice(&[0; 9]);
 
-    Ok(pixbuf)
-}
\ No newline at end of file
+        assert!(ppm_to_surface(&ppm).is_err());
+    }
+}
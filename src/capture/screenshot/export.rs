@@ -1,18 +1,156 @@
-use cairo::{Context, Format, ImageSurface};
+use cairo::Operator;
 
-use crate::modules::screenshot::state::ScreenshotState;
+use crate::common::tiled_surface::TiledSurface;
+use crate::config::{ExportConfig, RegionLayout};
+use crate::modules::screenshot::render;
+use crate::modules::screenshot::state::{Rect, Region, ScreenshotState, Spotlight};
 
-pub fn export_selection(original: &ImageSurface, state: &ScreenshotState) -> anyhow::Result<Vec<u8>> {
+pub fn export_selection(original: &TiledSurface, state: &ScreenshotState) -> anyhow::Result<Vec<u8>> {
+    let regions = state.regions();
+    let spotlights = state.spotlights();
 
-    let rect = state.selection().rect();
-    
-    let cropped = ImageSurface::create(Format::ARgb32, rect.w, rect.h)?;
-    let cr = Context::new(&cropped)?;
+    match regions.as_slice() {
+        [] => anyhow::bail!("Nothing is selected"),
+        [Region::Rect(rect)] if spotlights.is_empty() => export_rect(original, rect),
+        _ => export_regions(original, &regions, state.export_config(), (&spotlights, state.spotlight_opacity())),
+    }
+}
 
-    cr.set_source_surface(original, -rect.x as f64, -rect.y as f64)?;
-    cr.paint()?;
+pub fn export_rect(original: &TiledSurface, rect: &Rect) -> anyhow::Result<Vec<u8>> {
+
+    let mut cropped = TiledSurface::new(rect.w, rect.h)?;
+    cropped.draw(cropped.bounds(), |cr| {
+        cr.set_operator(Operator::Source);
+        original.paint(cr, -rect.x as f64, -rect.y as f64)
+    })?;
+
+    let mut buf = Vec::new();
+    cropped.write_png(&mut buf)?;
+    Ok(buf)
+}
+
+/// Combines one or more regions into one image on a transparent background.
+/// Freeform regions are clipped to their outline, and each region is dimmed
+/// outside the spotlights at the given opacity.
+fn export_regions(
+    original: &TiledSurface,
+    regions: &[Region],
+    config: &ExportConfig,
+    (spotlights, opacity): (&[Spotlight], u8),
+) -> anyhow::Result<Vec<u8>> {
+
+    let bounds: Vec<Rect> = regions.iter().map(Region::bounds).collect();
+    let (size, placements) = layout_regions(&bounds, config);
+    let mut composed = TiledSurface::new(size.0, size.1)?;
+
+    composed.draw(composed.bounds(), |cr| {
+        for (index, (x, y)) in &placements {
+            let rect = &bounds[*index];
+            cr.save()?;
+            match &regions[*index] {
+                Region::Rect(_) => cr.rectangle(*x as f64, *y as f64, rect.w as f64, rect.h as f64),
+                Region::Outline(points) => {
+                    let (dx, dy) = ((x - rect.x) as f64, (y - rect.y) as f64);
+                    for (px, py) in points {
+                        cr.line_to(px + dx, py + dy);
+                    }
+                    cr.close_path();
+                }
+            }
+            cr.clip();
+            original.paint(cr, (x - rect.x) as f64, (y - rect.y) as f64)?;
+
+            cr.translate((x - rect.x) as f64, (y - rect.y) as f64);
+            render::draw_spotlight(cr, &regions[*index], spotlights, opacity);
+            cr.restore()?;
+        }
+        Ok(())
+    })?;
 
     let mut buf = Vec::new();
-    cropped.write_to_png(&mut buf)?;
+    composed.write_png(&mut buf)?;
     Ok(buf)
-}
\ No newline at end of file
+}
+
+/// Index of a source region and its top-left corner in the exported image.
+type Placement = (usize, (i32, i32));
+
+/// Returns the output size and where each region is placed in it.
+fn layout_regions(regions: &[Rect], config: &ExportConfig) -> ((i32, i32), Vec<Placement>) {
+    match config.regions_layout {
+        RegionLayout::Preserve => {
+            let bounds = regions.iter()
+                .skip(1)
+                .fold(regions[0], |acc, r| acc.union(r));
+
+            let placements = regions.iter()
+                .enumerate()
+                .map(|(index, r)| (index, (r.x - bounds.x, r.y - bounds.y)))
+                .collect();
+
+            ((bounds.w, bounds.h), placements)
+        }
+
+        RegionLayout::Stack => {
+            let mut sorted: Vec<usize> = (0..regions.len()).collect();
+            sorted.sort_by_key(|&index| (regions[index].y, regions[index].x));
+
+            let padding = config.region_padding.max(0);
+            let width = regions.iter().map(|r| r.w).max().unwrap_or(1);
+            let mut y = 0;
+
+            let placements = sorted.into_iter()
+                .map(|index| {
+                    let placement = (index, (0, y));
+                    y += regions[index].h + padding;
+                    placement
+                })
+                .collect();
+
+            ((width, y - padding), placements)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(regions_layout: RegionLayout, region_padding: i32) -> ExportConfig {
+        ExportConfig { regions_layout, region_padding }
+    }
+
+    #[test]
+    fn preserved_regions_keep_their_offsets() {
+        let regions = [Rect { x: 100, y: 50, w: 40, h: 30 }, Rect { x: 20, y: 200, w: 10, h: 10 }];
+
+        let (size, placements) = layout_regions(&regions, &config(RegionLayout::Preserve, 16));
+
+        assert_eq!(size, (120, 160));
+        assert_eq!(placements, [(0, (80, 0)), (1, (0, 150))]);
+    }
+
+    #[test]
+    fn stacked_regions_follow_reading_order() {
+        let regions = [
+            Rect { x: 0, y: 300, w: 50, h: 20 },
+            Rect { x: 500, y: 10, w: 80, h: 40 },
+            Rect { x: 100, y: 10, w: 30, h: 10 },
+        ];
+
+        let (size, placements) = layout_regions(&regions, &config(RegionLayout::Stack, 16));
+
+        assert_eq!(size, (80, 102));
+        assert_eq!(placements, [(2, (0, 0)), (1, (0, 26)), (0, (0, 82))]);
+    }
+
+    #[test]
+    fn negative_padding_stacks_regions_flush() {
+        let regions = [Rect { x: 0, y: 0, w: 10, h: 10 }, Rect { x: 0, y: 50, w: 10, h: 20 }];
+
+        let (size, placements) = layout_regions(&regions, &config(RegionLayout::Stack, -5));
+
+        assert_eq!(size, (10, 30));
+        assert_eq!(placements, [(0, (0, 0)), (1, (0, 10))]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::capture::screenshot::capture;
+use crate::common::hyprland;
+use crate::config::HyprlandConfig;
+use crate::modules::screenshot::state::Rect;
+
+/// Temporarily blacks out configured layers and pauses animations so they
+/// don't end up frozen into the capture. Hyprland has no rule that removes a
+/// layer from screencopy, so `noscreenshare` layers show as black boxes.
+/// Exactly what was changed is restored on drop, which also covers a failing
+/// capture.
+pub struct CaptureHygiene {
+    /// Namespace patterns of the layer rules added, see [`rule_target`].
+    hidden_layers: Vec<String>,
+    /// Value of `animations:enabled` before it was turned off.
+    animations: Option<i64>,
+}
+
+impl CaptureHygiene {
+    pub fn apply(config: &HyprlandConfig) -> Option<Self> {
+        if !hyprland::is_running()
+            || (config.hide_layers.is_empty() && !config.disable_animations)
+        {
+            return None;
+        }
+
+        let mut commands = Vec::new();
+
+        let animations = if config.disable_animations {
+            match hyprland::get_option_int("animations:enabled") {
+                Ok(previous) => {
+                    commands.push(hyprland::keyword("animations:enabled", "0"));
+                    Some(previous)
+                }
+                Err(err) => {
+                    eprintln!("hyprshot: {err:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let hidden_layers: Vec<String> = config.hide_layers.iter().map(|namespace| rule_target(namespace)).collect();
+        for target in &hidden_layers {
+            commands.push(hyprland::keyword("layerrule", &format!("noscreenshare, {target}")));
+        }
+
+        let hygiene = Self { hidden_layers, animations };
+
+        if let Err(err) = hyprland::batch(&commands) {
+            eprintln!("hyprshot: {err:#}");
+        }
+
+        wait_for_frame(&config.hide_layers, Duration::from_millis(config.settle_delay_ms));
+
+        Some(hygiene)
+    }
+
+    fn restore(&self) -> anyhow::Result<()> {
+        let mut commands: Vec<String> = self.hidden_layers.iter()
+            .map(|target| hyprland::keyword("layerrule", &format!("unset, {target}")))
+            .collect();
+
+        if let Some(previous) = self.animations {
+            commands.push(hyprland::keyword("animations:enabled", &previous.to_string()));
+        }
+
+        hyprland::batch(&commands)
+    }
+}
+
+/// Waits until a frame has been rendered with the changes applied, or
+/// `timeout` has passed. Hyprland answers screencopy requests from the next
+/// frame it renders, so a finished capture means a new frame went out, and
+/// a hidden layer is done once it comes back blacked out. Only layers whose
+/// namespace is listed literally can be found; patterns are not waited for.
+fn wait_for_frame(namespaces: &[String], timeout: Duration) {
+    const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+    let deadline = Instant::now() + timeout;
+    let mut pending = match hyprland::layers() {
+        Ok(layers) => layers.iter()
+            .filter(|layer| namespaces.contains(&layer.namespace))
+            .map(hyprland::Layer::logical_rect)
+            .filter(|rect| !rect.is_empty())
+            .collect(),
+        Err(err) => {
+            eprintln!("hyprshot: {err:#}");
+            Vec::new()
+        }
+    };
+
+    // Without a hidden layer to watch, any finished capture shows that a frame went out
+    if pending.is_empty() {
+        let probe = hyprland::monitors().and_then(|monitors| {
+            let monitor = monitors.first().context("Hyprland reported no monitors")?;
+            capture::is_blacked_out(&Rect { w: 1, h: 1, ..monitor.logical_rect() })
+        });
+
+        if let Err(err) = probe {
+            eprintln!("hyprshot: {err:#}");
+        }
+        return;
+    }
+
+    loop {
+        pending.retain(|rect| !capture::is_blacked_out(rect).unwrap_or_else(|err| {
+            eprintln!("hyprshot: {err:#}");
+            true
+        }));
+
+        if pending.is_empty() {
+            return;
+        }
+
+        if Instant::now() >= deadline {
+            eprintln!("hyprshot: {} hidden layer(s) still visible after {timeout:?}", pending.len());
+            return;
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Pattern for the layer rule hiding `namespace`. It matches the same layers,
+/// but the extra group makes it differ from any pattern in the user's config,
+/// and `layerrule unset` removes every rule with the same pattern.
+fn rule_target(namespace: &str) -> String {
+    format!("(?:{namespace})")
+}
+
+impl Drop for CaptureHygiene {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore() {
+            eprintln!("hyprshot: failed to restore Hyprland state: {err:#}");
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod export;
-pub mod capture;
\ No newline at end of file
+pub mod capture;
+mod hygiene;
+mod redact;
\ No newline at end of file
@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::common::hyprland::{self, Layout};
+use crate::common::tiled_surface::TiledSurface;
+use crate::config::{RedactConfig, RedactStyle};
+use crate::modules::screenshot::render;
+use crate::modules::screenshot::state::{Rect, Shape};
+
+/// Covers every visible window matching the redaction rules.
+pub fn redact_sensitive(surface: &mut TiledSurface, config: &RedactConfig) -> Result<()> {
+    if config.is_empty() || !hyprland::is_running() {
+        return Ok(());
+    }
+
+    let monitors = hyprland::monitors()?;
+    let Some(layout) = Layout::new(&monitors, surface.width()) else { return Ok(()); };
+
+    let visible_workspaces: HashSet<i64> = monitors.iter()
+        .flat_map(|m| [m.active_workspace.id, m.special_workspace.id])
+        .collect();
+
+    for client in hyprland::clients()? {
+        if !client.mapped
+            || client.hidden
+            || !visible_workspaces.contains(&client.workspace.id)
+            || !config.matches(&client.class, &client.title)
+        {
+            continue;
+        }
+
+        let rect = layout.capture_rect(&client.logical_rect());
+        if let Some(rect) = rect.intersection(&surface.bounds()) {
+            redact_rect(surface, &rect, config.style)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn redact_rect(surface: &mut TiledSurface, rect: &Rect, style: RedactStyle) -> Result<()> {
+    match style {
+        RedactStyle::Blur => render::bake_shape(surface, &Shape::Blur { rect: *rect }),
+        RedactStyle::Solid => render::bake_shape(surface, &Shape::Redact { rect: *rect }),
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::modules::screenshot::state::Rect;
+
+/// Returns `true` when running inside a Hyprland session.
+pub fn is_running() -> bool {
+    std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some()
+}
+
+fn socket_path() -> Result<PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("hypr"))
+        .unwrap_or_else(|_| PathBuf::from("/tmp/hypr"));
+
+    Ok(runtime_dir.join(signature).join(".socket.sock"))
+}
+
+/// Sends a raw request over the Hyprland IPC socket and returns the reply.
+pub fn request(command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(socket_path()?)
+        .context("Failed to connect to the Hyprland socket")?;
+
+    stream.write_all(command.as_bytes())
+        .context("Failed to write Hyprland request")?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)
+        .context("Failed to read Hyprland reply")?;
+
+    Ok(reply)
+}
+
+/// Sends several commands in one roundtrip.
+pub fn batch(commands: &[String]) -> Result<()> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let reply = request(&format!("[[BATCH]]{}", commands.join(";")))?;
+
+    anyhow::ensure!(
+        reply.split_whitespace().all(|r| r == "ok"),
+        "Hyprland rejected batch request: {}", reply.trim()
+    );
+
+    Ok(())
+}
+
+pub fn keyword(name: &str, value: &str) -> String {
+    format!("keyword {name} {value}")
+}
+
+/// Reads an integer option, e.g. `animations:enabled`.
+pub fn get_option_int(name: &str) -> Result<i64> {
+    let reply = request(&format!("j/getoption {name}"))?;
+    let value: serde_json::Value = serde_json::from_str(&reply)
+        .with_context(|| format!("Unexpected getoption reply: {}", reply.trim()))?;
+
+    value["int"].as_i64()
+        .with_context(|| format!("Option {name} is not an integer"))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceRef {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub scale: f64,
+    pub transform: i32,
+    pub active_workspace: WorkspaceRef,
+    pub special_workspace: WorkspaceRef,
+}
+
+impl Monitor {
+    /// Monitor geometry in layout (logical) coordinates.
+    pub fn logical_rect(&self) -> Rect {
+        // Odd transforms rotate the output by 90 or 270 degrees
+        let (w, h) = if self.transform % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: (w as f64 / self.scale).round() as i32,
+            h: (h as f64 / self.scale).round() as i32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Client {
+    pub at: (i32, i32),
+    pub size: (i32, i32),
+    pub workspace: WorkspaceRef,
+    pub class: String,
+    pub title: String,
+    pub mapped: bool,
+    pub hidden: bool,
+}
+
+impl Client {
+    pub fn logical_rect(&self) -> Rect {
+        Rect { x: self.at.0, y: self.at.1, w: self.size.0, h: self.size.1 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Layer {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    pub namespace: String,
+}
+
+impl Layer {
+    pub fn logical_rect(&self) -> Rect {
+        Rect { x: self.x, y: self.y, w: self.w, h: self.h }
+    }
+}
+
+/// Layers of one monitor, keyed by level (background to overlay).
+#[derive(Debug, Deserialize)]
+struct MonitorLayers {
+    levels: HashMap<String, Vec<Layer>>,
+}
+
+pub fn monitors() -> Result<Vec<Monitor>> {
+    let reply = request("j/monitors")?;
+    serde_json::from_str(&reply).context("Failed to parse Hyprland monitors")
+}
+
+pub fn clients() -> Result<Vec<Client>> {
+    let reply = request("j/clients")?;
+    serde_json::from_str(&reply).context("Failed to parse Hyprland clients")
+}
+
+/// Mapped layer surfaces on every monitor.
+pub fn layers() -> Result<Vec<Layer>> {
+    let reply = request("j/layers")?;
+    let monitors: HashMap<String, MonitorLayers> = serde_json::from_str(&reply)
+        .context("Failed to parse Hyprland layers")?;
+
+    Ok(monitors.into_values().flat_map(|monitor| monitor.levels.into_values().flatten()).collect())
+}
+
+/// Maps layout coordinates onto pixels of a grim capture spanning all monitors.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    origin: (i32, i32),
+    scale: f64,
+}
+
+impl Layout {
+    pub fn new(monitors: &[Monitor], capture_width: i32) -> Option<Self> {
+        let rects: Vec<Rect> = monitors.iter().map(Monitor::logical_rect).collect();
+
+        let left = rects.iter().map(|r| r.x).min()?;
+        let top = rects.iter().map(|r| r.y).min()?;
+        let right = rects.iter().map(|r| r.x + r.w).max()?;
+
+        // grim renders the whole layout at the highest output scale
+        let scale = capture_width as f64 / (right - left).max(1) as f64;
+
+        Some(Self { origin: (left, top), scale })
+    }
+
+    pub fn capture_rect(&self, rect: &Rect) -> Rect {
+        let x1 = ((rect.x - self.origin.0) as f64 * self.scale).floor() as i32;
+        let y1 = ((rect.y - self.origin.1) as f64 * self.scale).floor() as i32;
+        let x2 = ((rect.x + rect.w - self.origin.0) as f64 * self.scale).ceil() as i32;
+        let y2 = ((rect.y + rect.h - self.origin.1) as f64 * self.scale).ceil() as i32;
+
+        Rect { x: x1, y: y1, w: x2 - x1, h: y2 - y1 }
+    }
+}
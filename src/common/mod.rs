@@ -1,2 +1,5 @@
 pub mod cursor;
-pub mod cairo_blur;
\ No newline at end of file
+pub mod cairo_blur;
+pub mod hyprland;
+pub mod tiled_surface;
+pub mod timing;
\ No newline at end of file
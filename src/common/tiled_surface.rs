@@ -0,0 +1,321 @@
+use std::io::Write;
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use cairo::{Context, Format, ImageSurface};
+use image::ImageEncoder;
+
+use crate::modules::screenshot::state::Rect;
+
+/// Edge length of a single tile. Well below Cairo's 32767px surface limit,
+/// so a capture of any size is split into surfaces Cairo can handle, and
+/// small enough that copies only duplicate the tiles they change.
+const TILE_SIZE: i32 = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub surface: ImageSurface,
+}
+
+impl Tile {
+    pub fn rect(&self) -> Rect {
+        Rect { x: self.x, y: self.y, w: self.surface.width(), h: self.surface.height() }
+    }
+
+    /// Copy of the tile with pixels of its own.
+    fn duplicate(&self) -> Result<Self> {
+        let surface = ImageSurface::create(Format::ARgb32, self.surface.width(), self.surface.height())
+            .context("Failed to create tile surface")?;
+
+        {
+            let cr = Context::new(&surface)?;
+            cr.set_operator(cairo::Operator::Source);
+            cr.set_source_surface(&self.surface, 0.0, 0.0)?;
+            cr.paint()?;
+        }
+
+        Ok(Self { x: self.x, y: self.y, surface })
+    }
+}
+
+/// An ARGB32 image of arbitrary size, stored as a grid of Cairo image surfaces.
+/// Clones share their tiles, and a shared tile is only copied once either
+/// side writes to it, so copies of a large capture stay cheap.
+#[derive(Debug, Clone)]
+pub struct TiledSurface {
+    width: i32,
+    height: i32,
+    tiles: Vec<Rc<Tile>>,
+}
+
+impl TiledSurface {
+    pub fn new(width: i32, height: i32) -> Result<Self> {
+        anyhow::ensure!(width > 0 && height > 0, "Invalid surface size {width}x{height}");
+
+        let mut tiles = Vec::new();
+
+        for y in (0..height).step_by(TILE_SIZE as usize) {
+            for x in (0..width).step_by(TILE_SIZE as usize) {
+                let w = TILE_SIZE.min(width - x);
+                let h = TILE_SIZE.min(height - y);
+                let surface = ImageSurface::create(Format::ARgb32, w, h)
+                    .context("Failed to create tile surface")?;
+
+                tiles.push(Rc::new(Tile { x, y, surface }));
+            }
+        }
+
+        Ok(Self { width, height, tiles })
+    }
+
+    pub fn width(&self) -> i32 { self.width }
+    pub fn height(&self) -> i32 { self.height }
+    pub fn bounds(&self) -> Rect { Rect { x: 0, y: 0, w: self.width, h: self.height } }
+    pub fn tiles(&self) -> impl Iterator<Item = &Tile> { self.tiles.iter().map(|tile| &**tile) }
+
+    /// Tiles for writing pixels directly, copying any still shared with a clone.
+    pub fn tiles_mut(&mut self) -> Result<impl Iterator<Item = &mut Tile>> {
+        for tile in &mut self.tiles {
+            Self::unshare(tile)?;
+        }
+
+        Ok(self.tiles.iter_mut().map(|tile| Rc::get_mut(tile).expect("Unshared above")))
+    }
+
+    /// Gives `tile` pixels of its own if a clone still shares them.
+    fn unshare(tile: &mut Rc<Tile>) -> Result<&mut Tile> {
+        if Rc::get_mut(tile).is_none() {
+            *tile = Rc::new(tile.duplicate()?);
+        }
+
+        Ok(Rc::get_mut(tile).expect("Unshared above"))
+    }
+
+    /// Paints every tile onto `cr` with the surface origin placed at (`x`, `y`).
+    pub fn paint(&self, cr: &Context, x: f64, y: f64) -> Result<()> {
+        for tile in &self.tiles {
+            let (tx, ty, tw, th) = tile.rect().as_f64();
+
+            cr.set_source_surface(&tile.surface, x + tx, y + ty)?;
+            cr.rectangle(x + tx, y + ty, tw, th);
+            cr.fill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `draw` once for every tile intersecting `area`, with the context
+    /// translated so that it can draw in whole-surface coordinates.
+    pub fn draw<F>(&mut self, area: Rect, draw: F) -> Result<()>
+    where
+        F: Fn(&Context) -> Result<()>,
+    {
+        for tile in &mut self.tiles {
+            if tile.rect().intersection(&area).is_none() {
+                continue;
+            }
+
+            let tile = Self::unshare(tile)?;
+            let cr = Context::new(&tile.surface)?;
+            cr.translate(-tile.x as f64, -tile.y as f64);
+            draw(&cr)?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies `rect` into a standalone surface. Areas outside the image stay transparent.
+    pub fn region(&self, rect: &Rect) -> Result<ImageSurface> {
+        let surface = ImageSurface::create(Format::ARgb32, rect.w, rect.h)
+            .context("Failed to create region surface")?;
+
+        {
+            let cr = Context::new(&surface)?;
+            cr.set_operator(cairo::Operator::Source);
+            self.paint(&cr, -rect.x as f64, -rect.y as f64)?;
+        }
+
+        Ok(surface)
+    }
+
+    /// Replaces the pixels at (`x`, `y`) with the contents of `region`.
+    pub fn write_region(&mut self, region: &ImageSurface, x: i32, y: i32) -> Result<()> {
+        let area = Rect { x, y, w: region.width(), h: region.height() };
+
+        self.draw(area, |cr| {
+            let (x, y, w, h) = area.as_f64();
+
+            cr.set_operator(cairo::Operator::Source);
+            cr.set_source_surface(region, x, y)?;
+            cr.rectangle(x, y, w, h);
+            cr.fill()?;
+            Ok(())
+        })
+    }
+
+    /// Encodes the whole image as PNG without going through a single Cairo surface.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<()> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut rgba = vec![0u8; width * height * 4];
+
+        for tile in &self.tiles {
+            let (tw, stride) = (tile.surface.width() as usize, tile.surface.stride() as usize);
+
+            tile.surface.with_data(|data| {
+                for (row, src) in data.chunks(stride).enumerate().take(tile.surface.height() as usize) {
+                    let offset = ((tile.y as usize + row) * width + tile.x as usize) * 4;
+                    let dst = &mut rgba[offset..offset + tw * 4];
+
+                    for (argb, out) in src[..tw * 4].chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+                        let pixel = u32::from_ne_bytes([argb[0], argb[1], argb[2], argb[3]]);
+                        out.copy_from_slice(&unpremultiply(pixel));
+                    }
+                }
+            })?;
+        }
+
+        image::codecs::png::PngEncoder::new(writer)
+            .write_image(&rgba, self.width as u32, self.height as u32, image::ColorType::Rgba8)
+            .context("Failed to encode PNG")?;
+
+        Ok(())
+    }
+}
+
+fn unpremultiply(pixel: u32) -> [u8; 4] {
+    let a = (pixel >> 24) & 0xff;
+    let channel = |shift: u32| {
+        let c = (pixel >> shift) & 0xff;
+        match a {
+            0 => 0,
+            255 => c as u8,
+            _ => ((c * 255 + a / 2) / a).min(255) as u8,
+        }
+    };
+
+    [channel(16), channel(8), channel(0), a as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Surface whose pixels encode their own coordinates.
+    fn gradient(width: i32, height: i32) -> TiledSurface {
+        let mut surface = TiledSurface::new(width, height).unwrap();
+
+        for tile in surface.tiles_mut().unwrap() {
+            let stride = tile.surface.stride() as usize;
+            let (tx, ty, tw) = (tile.x, tile.y, tile.surface.width() as usize);
+            let mut data = tile.surface.data().unwrap();
+
+            for (row, line) in data.chunks_exact_mut(stride).enumerate() {
+                for (col, argb) in line[..tw * 4].chunks_exact_mut(4).enumerate() {
+                    argb.copy_from_slice(&coded(tx + col as i32, ty + row as i32).to_ne_bytes());
+                }
+            }
+        }
+
+        surface
+    }
+
+    fn coded(x: i32, y: i32) -> u32 {
+        0xff00_0000 | ((x as u32 & 0xfff) << 12) | (y as u32 & 0xfff)
+    }
+
+    fn pixels(region: &ImageSurface) -> Vec<u32> {
+        let (width, stride) = (region.width() as usize, region.stride() as usize);
+        let mut pixels = Vec::new();
+
+        region.with_data(|data| {
+            for line in data.chunks(stride).take(region.height() as usize) {
+                pixels.extend(line[..width * 4].chunks_exact(4).map(|p| u32::from_ne_bytes([p[0], p[1], p[2], p[3]])));
+            }
+        }).unwrap();
+
+        pixels
+    }
+
+    #[test]
+    fn large_surfaces_are_split_into_tiles() {
+        let surface = TiledSurface::new(TILE_SIZE + 10, 20).unwrap();
+        let rects: Vec<_> = surface.tiles().map(Tile::rect).collect();
+
+        assert_eq!(rects, [
+            Rect { x: 0, y: 0, w: TILE_SIZE, h: 20 },
+            Rect { x: TILE_SIZE, y: 0, w: 10, h: 20 },
+        ]);
+        assert!(TiledSurface::new(0, 20).is_err());
+    }
+
+    #[test]
+    fn region_spans_tile_borders() {
+        let surface = gradient(TILE_SIZE + 10, 4);
+        let rect = Rect { x: TILE_SIZE - 2, y: 1, w: 4, h: 2 };
+
+        let expected: Vec<_> = (1..3)
+            .flat_map(|y| (TILE_SIZE - 2..TILE_SIZE + 2).map(move |x| coded(x, y)))
+            .collect();
+
+        assert_eq!(pixels(&surface.region(&rect).unwrap()), expected);
+    }
+
+    #[test]
+    fn region_outside_the_image_is_transparent() {
+        let surface = gradient(4, 4);
+        let region = surface.region(&Rect { x: 3, y: -1, w: 2, h: 2 }).unwrap();
+
+        assert_eq!(pixels(&region), [0, 0, coded(3, 0), 0]);
+    }
+
+    #[test]
+    fn written_region_reads_back_across_tiles() {
+        let mut surface = TiledSurface::new(8, TILE_SIZE + 8).unwrap();
+        let source = gradient(8, TILE_SIZE + 8);
+        let rect = Rect { x: 2, y: TILE_SIZE - 3, w: 4, h: 6 };
+
+        let region = source.region(&rect).unwrap();
+        surface.write_region(&region, rect.x, rect.y).unwrap();
+
+        assert_eq!(pixels(&surface.region(&rect).unwrap()), pixels(&region));
+        assert_eq!(pixels(&surface.region(&Rect { x: 1, y: TILE_SIZE - 3, w: 1, h: 1 }).unwrap()), [0]);
+    }
+
+    #[test]
+    fn clone_and_png_keep_every_tile() {
+        let surface = gradient(TILE_SIZE + 3, 2).clone();
+        let mut png = Vec::new();
+        surface.write_png(&mut png).unwrap();
+
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        let corner = coded(TILE_SIZE + 2, 1);
+
+        assert_eq!((image.width(), image.height()), (TILE_SIZE as u32 + 3, 2));
+        assert_eq!(image.get_pixel(TILE_SIZE as u32 + 2, 1).0, [(corner >> 16) as u8, (corner >> 8) as u8, corner as u8, 255]);
+    }
+
+    #[test]
+    fn unpremultiply_restores_straight_alpha() {
+        assert_eq!(unpremultiply(0x8040_2000), [0x80, 0x40, 0x00, 0x80]);
+        assert_eq!(unpremultiply(0x0012_3456), [0, 0, 0, 0]);
+        assert_eq!(unpremultiply(0xff12_3456), [0x12, 0x34, 0x56, 0xff]);
+    }
+
+    #[test]
+    fn clones_share_tiles_until_written() {
+        let original = gradient(TILE_SIZE * 2, 2);
+        let mut copy = original.clone();
+
+        let blank = TiledSurface::new(1, 1).unwrap().region(&Rect { x: 0, y: 0, w: 1, h: 1 }).unwrap();
+        copy.write_region(&blank, TILE_SIZE, 0).unwrap();
+
+        let pixel = |surface: &TiledSurface| pixels(&surface.region(&Rect { x: TILE_SIZE, y: 0, w: 1, h: 1 }).unwrap());
+        assert_eq!(pixel(&copy), [0]);
+        assert_eq!(pixel(&original), [coded(TILE_SIZE, 0)]);
+
+        assert!(Rc::ptr_eq(&original.tiles[0], &copy.tiles[0]));
+        assert!(!Rc::ptr_eq(&original.tiles[1], &copy.tiles[1]));
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+/// Records how long each startup phase takes.
+/// Output goes to stderr and is enabled with `HYPRSHOT_TIMINGS=1`.
+#[derive(Debug)]
+pub struct PhaseTimer {
+    enabled: bool,
+    start: Instant,
+    last: Instant,
+}
+
+impl PhaseTimer {
+    pub fn from_env() -> Self {
+        let now = Instant::now();
+
+        Self {
+            enabled: std::env::var_os("HYPRSHOT_TIMINGS").is_some_and(|v| v != "0"),
+            start: now,
+            last: now,
+        }
+    }
+
+    pub fn mark(&mut self, phase: &str) {
+        let now = Instant::now();
+
+        if self.enabled {
+            eprintln!(
+                "[timing] {:<12} {:>8.2} ms (total {:>8.2} ms)",
+                phase,
+                (now - self.last).as_secs_f64() * 1000.0,
+                (now - self.start).as_secs_f64() * 1000.0,
+            );
+        }
+
+        self.last = now;
+    }
+}
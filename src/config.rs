@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// User configuration read from `$XDG_CONFIG_HOME/hyprshot/config.toml`.
+/// Every section is optional; missing values fall back to the defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub hyprland: HyprlandConfig,
+    pub redact: RedactConfig,
+    pub export: ExportConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HyprlandConfig {
+    /// Layer namespaces blacked out while capturing, e.g. `notifications` or `waybar`.
+    pub hide_layers: Vec<String>,
+    pub disable_animations: bool,
+    /// Longest wait for the compositor to render a frame with the hidden
+    /// layers blacked out. The capture goes ahead as soon as it has.
+    pub settle_delay_ms: u64,
+}
+
+impl Default for HyprlandConfig {
+    fn default() -> Self {
+        Self {
+            hide_layers: Vec::new(),
+            disable_animations: false,
+            settle_delay_ms: 500,
+        }
+    }
+}
+
+/// Windows that are covered automatically before the capture is used anywhere.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    /// Window classes matched exactly, ignoring case.
+    pub classes: Vec<String>,
+    /// Substrings matched against window titles.
+    pub titles: Vec<String>,
+    pub style: RedactStyle,
+}
+
+impl RedactConfig {
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty() && self.titles.is_empty()
+    }
+
+    pub fn matches(&self, class: &str, title: &str) -> bool {
+        self.classes.iter().any(|c| c.eq_ignore_ascii_case(class))
+            || self.titles.iter().any(|t| title.contains(t.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactStyle {
+    /// Opaque black box. Nothing of the window is left in the capture.
+    #[default]
+    Solid,
+    /// Gaussian blur. Weaker: large or high-contrast text can still be read back.
+    Blur,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// How several selected regions are combined into one image.
+    pub regions_layout: RegionLayout,
+    /// Gap between regions when they are stacked.
+    pub region_padding: i32,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            regions_layout: RegionLayout::Preserve,
+            region_padding: 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionLayout {
+    /// Keep relative positions on a transparent canvas.
+    #[default]
+    Preserve,
+    /// Stack regions top to bottom with padding in between.
+    Stack,
+}
+
+impl Config {
+    /// Loads the config file, falling back to defaults when it is missing or invalid.
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("hyprshot: {err:#}, using default configuration");
+                Self::default()
+            }
+        }
+    }
+
+    fn try_load() -> Result<Self> {
+        let Some(path) = Self::path() else { return Ok(Self::default()); };
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(base.join("hyprshot").join("config.toml"))
+    }
+}
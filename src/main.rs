@@ -6,8 +6,11 @@ use gtk::{Application, gio};
 mod action;
 mod capture;
 mod common;
+mod config;
 mod modules;
 
+use self::config::Config;
+use self::modules::outputs::{self, OutputsArgs};
 use self::modules::screenshot;
 
 #[derive(Parser)]
@@ -19,7 +22,10 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Select an area interactively
     Screen,
+    /// List monitors, or capture each one into its own file with --all
+    Outputs(OutputsArgs),
 }
 
 const APP_ID: &str = "io.github.misery8.hyprshot";
@@ -38,14 +44,25 @@ fn main() {
         }
     };
 
+    let config = Config::load();
+
+    if let Commands::Outputs(args) = &command {
+        if let Err(err) = outputs::run(args, &config) {
+            eprintln!("hyprshot: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let app = Application::new(Some(APP_ID), gio::ApplicationFlags::FLAGS_NONE);
         
     app.connect_activate(move |app| {
 
         match &command {
             Commands::Screen => {
-                screenshot::run(app);
+                screenshot::run(app, &config);
             }
+            Commands::Outputs(_) => unreachable!("handled without a GTK application"),
         }
     });
 
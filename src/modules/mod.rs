@@ -1 +1,2 @@
-pub mod screenshot;
+pub mod outputs;
+pub mod screenshot;
\ No newline at end of file
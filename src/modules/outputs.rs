@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::capture::screenshot::{capture::capture_fullscreen, export::export_rect};
+use crate::common::hyprland::{self, Layout};
+use crate::common::timing::PhaseTimer;
+use crate::config::Config;
+
+#[derive(Debug, Args)]
+pub struct OutputsArgs {
+    /// Capture every monitor into its own image. Without it, monitors are only listed.
+    #[arg(long)]
+    all: bool,
+
+    /// Directory the images are written to
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+
+    /// File name prefix, followed by the monitor name [default: hyprshot-<unix time>]
+    #[arg(short, long)]
+    prefix: Option<String>,
+}
+
+pub fn run(args: &OutputsArgs, config: &Config) -> Result<()> {
+    let monitors = hyprland::monitors()?;
+
+    if !args.all {
+        for monitor in &monitors {
+            let rect = monitor.logical_rect();
+            println!("{}\t{},{} {}x{}", monitor.name, rect.x, rect.y, rect.w, rect.h);
+        }
+        return Ok(());
+    }
+
+    let mut timer = PhaseTimer::from_env();
+    let surface = capture_fullscreen(config, &mut timer)?;
+
+    let layout = Layout::new(&monitors, surface.width())
+        .context("No monitors reported by Hyprland")?;
+
+    let prefix = args.prefix.clone().unwrap_or_else(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        format!("hyprshot-{secs}")
+    });
+
+    std::fs::create_dir_all(&args.dir)
+        .with_context(|| format!("Failed to create {}", args.dir.display()))?;
+
+    for monitor in &monitors {
+        let rect = layout.capture_rect(&monitor.logical_rect());
+        let Some(rect) = rect.intersection(&surface.bounds()) else { continue; };
+
+        let png = export_rect(&surface, &rect)?;
+        let path = args.dir.join(format!("{prefix}-{}.png", monitor.name));
+
+        std::fs::write(&path, png)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("{}", path.display());
+    }
+    timer.mark("export");
+
+    Ok(())
+}
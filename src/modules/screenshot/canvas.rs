@@ -1,82 +1,233 @@
-use std::{cell::RefCell, rc::Rc, result::Result};
+use std::{cell::{OnceCell, Ref, RefCell}, rc::Rc, result::Result};
 
-use anyhow::{Ok, Error};
-use gdk::ffi::gdk_cairo_set_source_pixbuf;
-use cairo::{ImageSurface, Context};
-use glib::translate::ToGlibPtr;
+use gtk::gio;
 
-use crate::modules::screenshot::{render, state::{ScreenshotState, Shape, Tool}};
+use anyhow::Error;
 
-#[derive(Debug, Clone)]
+use crate::capture::screenshot::capture::capture_fullscreen;
+use crate::common::tiled_surface::TiledSurface;
+use crate::common::timing::PhaseTimer;
+use crate::config::Config;
+use crate::modules::screenshot::{render, snapping::{EdgeMap, Luminance}, state::Shape};
+
+#[derive(Debug)]
 pub struct Canvas {
-    pub surface: Rc<RefCell<ImageSurface>>,
-    history: RefCell<Vec<ImageSurface>>,
+    /// The capture as taken. Annotations are never drawn into it.
+    pub surface: Rc<RefCell<TiledSurface>>,
+    /// The capture with the annotations drawn over it, kept until they change.
+    /// Tiles no annotation touches are shared with `surface`, not copied.
+    composed: RefCell<Option<Composed>>,
+    /// Filled in the background by [`Canvas::detect_edges`].
+    edges: OnceCell<EdgeMap>,
+}
+
+#[derive(Debug)]
+struct Composed {
+    /// Revision of the shape list drawn, see `ScreenshotState::shapes_revision`.
+    revision: u64,
+    shapes: Vec<Shape>,
+    /// The capture with only the redactions drawn, sampled by blur and the
+    /// other effects. `None` while there are no redactions.
+    redacted: Option<TiledSurface>,
+    surface: TiledSurface,
 }
 
 impl Canvas {
-    pub fn from_screenshot() -> Result<Self, Error> {
-        let surface= Rc::new(RefCell::new(Self::prepare_background_surface()));
-        let history = RefCell::new(Vec::new());
+    pub fn from_screenshot(config: &Config, timer: &mut PhaseTimer) -> Result<Self, Error> {
+        let surface = capture_fullscreen(config, timer)?;
+        Ok(Self::new(surface))
+    }
+
+    pub fn new(surface: TiledSurface) -> Self {
+        let surface = Rc::new(RefCell::new(surface));
 
-        Ok(Self { surface, history })
+        Self { surface, composed: RefCell::new(None), edges: OnceCell::new() }
     }
 
-    fn prepare_background_surface() -> ImageSurface {
-        let pixbuf = crate::capture::screenshot::capture::capture_fullscreen()
-            .expect("Failed to capture screen");
+    /// Starts detecting the boundaries used for selection snapping on a
+    /// worker thread. Only reading the capture's luminance, which the worker
+    /// can't do itself, happens here, so call it once the overlay is shown.
+    pub fn detect_edges(self: &Rc<Self>) {
+        let luminance = match Luminance::from_surface(&self.surface.borrow()) {
+            Ok(luminance) => luminance,
+            Err(err) => {
+                eprintln!("hyprshot: edge detection failed: {err:#}");
+                return;
+            }
+        };
 
-        let surface = ImageSurface::create(cairo::Format::ARgb32, pixbuf.width(), pixbuf.height())
-            .expect("Failed to create surface");
+        let detection = gio::spawn_blocking(move || EdgeMap::detect(&luminance));
+        let canvas = Rc::downgrade(self);
 
-        {
-            let cr = Context::new(&surface)
-                .expect("Failed to create Cairo context");
-            unsafe {
-                gdk_cairo_set_source_pixbuf(cr.to_raw_none(), pixbuf.to_glib_none().0, 0.0, 0.0);
+        glib::spawn_future_local(async move {
+            if let (Ok(edges), Some(canvas)) = (detection.await, canvas.upgrade()) {
+                let _ = canvas.edges.set(edges);
             }
-            cr.paint().expect("Failet to paint pixbuf onto surface");
+        });
+    }
+
+    /// Boundaries used for selection snapping, once detection has finished.
+    pub fn edges(&self) -> Option<&EdgeMap> {
+        self.edges.get()
+    }
+
+    /// The capture with `shapes` drawn over it in order. Only the areas of
+    /// shapes that changed since the last call are drawn again.
+    pub fn composed(&self, shapes: &[Shape], revision: u64) -> Result<Ref<'_, TiledSurface>, Error> {
+        if shapes.is_empty() {
+            return Ok(self.surface.borrow());
         }
 
-        surface
+        self.update(shapes, revision)?;
+
+        Ok(Ref::map(self.composed.borrow(), |composed| {
+            &composed.as_ref().expect("Updated above").surface
+        }))
     }
 
-    pub fn save_shapshot(&self, state: &ScreenshotState) {
+    /// What effects such as blur sample: the capture with the redactions
+    /// drawn, as of the last call to [`Canvas::composed`].
+    pub fn sampled(&self) -> Ref<'_, TiledSurface> {
+        let composed = self.composed.borrow();
 
-        if state.current_tool() != Tool::None
-            && state.is_paused()
-        {
-            let surface = self.surface.borrow();
-            if let Result::Ok(backup) = Self::clone_surface(&surface) {
-                self.history.borrow_mut().push(backup);
-            }
+        if composed.as_ref().is_some_and(|composed| composed.redacted.is_some()) {
+            Ref::map(composed, |composed| {
+                composed.as_ref().and_then(|composed| composed.redacted.as_ref()).expect("Checked above")
+            })
+        } else {
+            self.surface.borrow()
         }
     }
 
-    pub fn restore_snapshot(&self) {
-        if let Some(previus_surface) = self.history.borrow_mut().pop() {
-            *self.surface.borrow_mut() = previus_surface;
+    fn update(&self, shapes: &[Shape], revision: u64) -> Result<(), Error> {
+        let source = self.surface.borrow();
+        let mut cache = self.composed.borrow_mut();
+
+        let Some(composed) = cache.as_mut() else {
+            let redacted = render::redact(&source, shapes)?;
+            let surface = render::compose(&source, redacted.as_ref().unwrap_or(&source), shapes)?;
+
+            *cache = Some(Composed { revision, shapes: shapes.to_vec(), redacted, surface });
+            return Ok(());
+        };
+
+        if composed.revision == revision {
+            return Ok(());
+        }
+
+        let changed = render::changed_shapes(&composed.shapes, shapes);
+        let mut areas: Vec<_> = changed.iter().map(|shape| shape.bounds()).collect();
+        let redacted_areas: Vec<_> = changed.iter()
+            .filter(|shape| render::is_redaction(shape))
+            .map(|shape| shape.bounds())
+            .collect();
+
+        if !redacted_areas.is_empty() {
+            let redactions: Vec<Shape> = shapes.iter().filter(|shape| render::is_redaction(shape)).cloned().collect();
+
+            if redactions.is_empty() {
+                composed.redacted = None;
+            } else if let Some(redacted) = &mut composed.redacted {
+                for area in &redacted_areas {
+                    render::redraw(redacted, &source, &source, &redactions, area)?;
+                }
+            } else {
+                composed.redacted = render::redact(&source, &redactions)?;
+            }
+
+            // Effects over a changed redaction sample different pixels now
+            areas.extend(shapes.iter()
+                .filter(|shape| render::samples_capture(shape))
+                .map(Shape::bounds)
+                .filter(|bounds| redacted_areas.iter().any(|area| area.intersection(bounds).is_some())));
+        }
+
+        let sampled = composed.redacted.as_ref().unwrap_or(&source);
+        for area in &areas {
+            render::redraw(&mut composed.surface, &source, sampled, shapes, area)?;
         }
+
+        composed.revision = revision;
+        composed.shapes = shapes.to_vec();
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::screenshot::export::export_rect;
+    use crate::modules::screenshot::state::{Rect, Style};
+
+    const SECRET: Rect = Rect { x: 10, y: 10, w: 40, h: 40 };
+
+    /// White capture for the tests to redact `SECRET` in.
+    fn white_canvas() -> Canvas {
+        let mut surface = TiledSurface::new(200, 100).unwrap();
+        surface.draw(surface.bounds(), |cr| {
+            cr.set_source_rgb(1.0, 1.0, 1.0);
+            cr.paint()?;
+            anyhow::Ok(())
+        }).unwrap();
+
+        Canvas::new(surface)
     }
 
-    pub fn clone_surface(surface: &ImageSurface) -> Result<ImageSurface, Error> {
-        let copy = ImageSurface::create(surface.format(), surface.width(), surface.height())?;
-        let cr = Context::new(&copy)?;
-        cr.set_source_surface(surface, 0.0, 0.0)?;
-        cr.paint()?;
-        
-        Ok(copy)
+    fn exported_pixel(canvas: &Canvas, shapes: &[Shape], revision: u64, (x, y): (u32, u32)) -> [u8; 4] {
+        let composed = canvas.composed(shapes, revision).unwrap();
+        let png = export_rect(&composed, &composed.bounds()).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+
+        image.get_pixel(x, y).0
     }
 
-    pub fn apply_shape(&self, shape: &Shape) {
-        
-        if shape.is_valid() {
-            let surface = self.surface.borrow_mut();
-            let cr = Context::new(&*surface)
-                .expect("Failed to bake context");
-            render::draw_shape(&*surface, &cr, shape);
-        } else {
-            self.history.borrow_mut().pop();
+    fn magnify() -> Shape {
+        Shape::Magnify {
+            source: Rect { x: 20, y: 20, w: 20, h: 20 },
+            target: Rect { x: 100, y: 20, w: 60, h: 60 },
+            color: (255, 0, 0),
+            connector: false,
+            style: Style::default(),
         }
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn magnify_over_redaction_stays_black() {
+        let canvas = white_canvas();
+        let shapes = vec![Shape::Redact { rect: SECRET }, magnify()];
+
+        assert_eq!(exported_pixel(&canvas, &shapes, 1, (130, 50)), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn blur_over_redaction_stays_black() {
+        let canvas = white_canvas();
+        let shapes = vec![Shape::Redact { rect: SECRET }, Shape::Blur { rect: Rect { x: 0, y: 0, w: 60, h: 60 } }];
+
+        assert_eq!(exported_pixel(&canvas, &shapes, 1, (30, 30)), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn redaction_added_later_covers_magnified_copy() {
+        let canvas = white_canvas();
+        let mut shapes = vec![magnify()];
+
+        assert_eq!(exported_pixel(&canvas, &shapes, 1, (130, 50)), [255, 255, 255, 255]);
+
+        shapes.insert(0, Shape::Redact { rect: SECRET });
+        assert_eq!(exported_pixel(&canvas, &shapes, 2, (130, 50)), [0, 0, 0, 255]);
+
+        shapes.remove(0);
+        assert_eq!(exported_pixel(&canvas, &shapes, 3, (130, 50)), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn unchanged_revision_is_not_redrawn() {
+        let canvas = white_canvas();
+        let shapes = vec![Shape::Redact { rect: SECRET }];
+
+        assert_eq!(exported_pixel(&canvas, &shapes, 1, (30, 30)), [0, 0, 0, 255]);
+        assert_eq!(exported_pixel(&canvas, &[Shape::Blur { rect: SECRET }], 1, (30, 30)), [0, 0, 0, 255]);
+    }
+}
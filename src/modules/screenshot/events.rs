@@ -1,13 +1,14 @@
 use std::sync::mpsc::Sender;
 
-use gdk::Key;
+use gdk::{Key, ModifierType};
 use glib::clone;
 use gtk::{
     EventControllerMotion, GestureDrag, EventControllerKey,
     Shortcut, CallbackAction, ShortcutController, ShortcutTrigger, prelude::*
 };
 
-use crate::action::{AppAction, GlobalAction, ScreenshotAction};
+use crate::action::{AppAction, ScreenshotAction};
+use crate::modules::screenshot::state::{Modifiers, SelectionHitZone, SelectionMode};
 use crate::modules::screenshot::ui::ScreenshotWidgets;
 
 pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
@@ -15,13 +16,15 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
     let drag = GestureDrag::new();
     drag.set_button(1);
 
-    drag.connect_drag_begin(clone!(#[strong] tx, move |_g, x, y| {
-            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::DragBegin(x as i32, y as i32)));
+    drag.connect_drag_begin(clone!(#[strong] tx, move |g, x, y| {
+            let modifiers = drag_modifiers(g.current_event_state());
+            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::DragBegin(x as i32, y as i32, modifiers)));
         }
     ));
 
-    drag.connect_drag_update(clone!(#[strong] tx, move |_g, dx, dy| {
-            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::DragUpdate(dx as i32, dy as i32)));
+    drag.connect_drag_update(clone!(#[strong] tx, move |g, dx, dy| {
+            let modifiers = drag_modifiers(g.current_event_state());
+            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::DragUpdate(dx as i32, dy as i32, modifiers)));
         }
     ));
 
@@ -47,7 +50,7 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
         Some(CallbackAction::new(clone!(
             #[strong] tx,
             move |_, _,| {
-                let _ = tx.send(AppAction::Global(GlobalAction::Quit));
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::Cancel));
                 glib::Propagation::Stop
             }
         )
@@ -65,6 +68,18 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
         )
     ))));
 
+    // Ctrl+Shift+Z, added first so Ctrl+Z doesn't take it
+    controller.add_shortcut(Shortcut::new(
+        Some(ShortcutTrigger::parse_string("<Primary><Shift>z").unwrap()),
+        Some(CallbackAction::new(clone!(
+            #[strong] tx,
+            move |_, _| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::Redo));
+                glib::Propagation::Stop
+            }
+        )))
+    ));
+
     // Ctrl+Z
     controller.add_shortcut(Shortcut::new(
         Some(ShortcutTrigger::parse_string("<Primary>z").unwrap()),
@@ -72,7 +87,7 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
             #[strong] tx,
             move |_, _| {
                 let _ = tx.send(AppAction::Screenshot(ScreenshotAction::Undo));
-                glib::Propagation::Proceed
+                glib::Propagation::Stop
             }
         )))
     ));
@@ -80,9 +95,9 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
     widgets.window.add_controller(controller);
 
     let key_controller = EventControllerKey::new();
-    key_controller.connect_key_pressed(clone!(#[strong] tx, move |_, key, _, _| {
-        if key == Key::Control_L || key == Key::Control_R {
-            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::ToogleMode));
+    key_controller.connect_key_pressed(clone!(#[strong] tx, move |_, key, _, modifiers| {
+        if let Some(action) = key_action(key, modifiers) {
+            let _ = tx.send(AppAction::Screenshot(action));
 
             glib::Propagation::Stop
         } else {
@@ -94,3 +109,64 @@ pub fn init_events(tx: Sender<AppAction>, widgets: &ScreenshotWidgets) {
     widgets.window.add_controller(key_controller);
 
 }
+
+fn drag_modifiers(state: ModifierType) -> Modifiers {
+    Modifiers {
+        shift: state.contains(ModifierType::SHIFT_MASK),
+        alt: state.contains(ModifierType::ALT_MASK),
+        ctrl: state.contains(ModifierType::CONTROL_MASK),
+    }
+}
+
+/// Maps key presses to actions.
+/// Arrows move the active handle, Alt+arrows grow the selection towards the
+/// arrow, Ctrl+arrows shrink it from the opposite side. Shift moves by 10px.
+/// R, L and P switch between rectangle, lasso and polygon selection, G opens
+/// the geometry editor. Delete removes the annotation picked with the select tool.
+fn key_action(key: Key, modifiers: ModifierType) -> Option<ScreenshotAction> {
+    let step = if modifiers.contains(ModifierType::SHIFT_MASK) { 10 } else { 1 };
+
+    let direction = match key {
+        Key::Left => Some((-1, 0)),
+        Key::Right => Some((1, 0)),
+        Key::Up => Some((0, -1)),
+        Key::Down => Some((0, 1)),
+        _ => None,
+    };
+
+    if let Some((x, y)) = direction {
+        let (dx, dy) = (x * step, y * step);
+
+        let action = if modifiers.contains(ModifierType::ALT_MASK) {
+            ScreenshotAction::NudgeEdge(edge_towards(x, y), dx, dy)
+        } else if modifiers.contains(ModifierType::CONTROL_MASK) {
+            ScreenshotAction::NudgeEdge(edge_towards(-x, -y), dx, dy)
+        } else {
+            ScreenshotAction::Nudge(dx, dy)
+        };
+
+        return Some(action);
+    }
+
+    match key {
+        Key::Control_L | Key::Control_R => Some(ScreenshotAction::ToogleMode),
+        Key::Tab => Some(ScreenshotAction::CycleHandle(true)),
+        Key::ISO_Left_Tab => Some(ScreenshotAction::CycleHandle(false)),
+        Key::r => Some(ScreenshotAction::SetSelectionMode(SelectionMode::Rectangle)),
+        Key::l => Some(ScreenshotAction::SetSelectionMode(SelectionMode::Lasso)),
+        Key::p => Some(ScreenshotAction::SetSelectionMode(SelectionMode::Polygon)),
+        Key::Return | Key::KP_Enter => Some(ScreenshotAction::ClosePolygon),
+        Key::g => Some(ScreenshotAction::OpenSelectionEditor),
+        Key::Delete | Key::BackSpace => Some(ScreenshotAction::DeleteShape),
+        _ => None,
+    }
+}
+
+fn edge_towards(x: i32, y: i32) -> SelectionHitZone {
+    match (x.signum(), y.signum()) {
+        (-1, _) => SelectionHitZone::W,
+        (1, _) => SelectionHitZone::E,
+        (_, -1) => SelectionHitZone::N,
+        _ => SelectionHitZone::S,
+    }
+}
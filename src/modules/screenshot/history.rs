@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use crate::modules::screenshot::state::Shape;
+
+/// Approximate memory the undo steps may take. The oldest are forgotten beyond it.
+const MEMORY_BUDGET: usize = 8 * 1024 * 1024;
+
+/// A reversible change to the annotation list.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// Puts a shape on top of the others.
+    Add(Shape),
+    Remove(usize, Shape),
+    /// Swaps the shape at the index, from the first version to the second.
+    Replace(usize, Shape, Shape),
+}
+
+impl Edit {
+    fn apply(&self, shapes: &mut Vec<Shape>) {
+        match self {
+            Edit::Add(shape) => shapes.push(shape.clone()),
+            Edit::Remove(index, _) => { shapes.remove(*index); }
+            Edit::Replace(index, _, after) => shapes[*index] = after.clone(),
+        }
+    }
+
+    fn revert(&self, shapes: &mut Vec<Shape>) {
+        match self {
+            Edit::Add(_) => { shapes.pop(); }
+            Edit::Remove(index, shape) => shapes.insert(*index, shape.clone()),
+            Edit::Replace(index, before, _) => shapes[*index] = before.clone(),
+        }
+    }
+
+    fn memory(&self) -> usize {
+        let shapes = match self {
+            Edit::Add(shape) | Edit::Remove(_, shape) => heap_size(shape),
+            Edit::Replace(_, before, after) => heap_size(before) + heap_size(after),
+        };
+        size_of::<Self>() + shapes
+    }
+}
+
+fn heap_size(shape: &Shape) -> usize {
+    match shape {
+        Shape::Stroke { points, .. } => points.capacity() * size_of::<(i32, i32)>(),
+        Shape::Text { text, font, .. } => text.capacity() + font.capacity(),
+        _ => 0,
+    }
+}
+
+/// A recorded edit with the step marker counter before and after it. Undo
+/// and redo only touch the counter if the edit changed it, so restarting
+/// the numbering isn't lost when unrelated edits are undone.
+#[derive(Debug, Clone)]
+struct Step {
+    edit: Edit,
+    next_marker: (u32, u32),
+}
+
+impl Step {
+    fn memory(&self) -> usize {
+        size_of::<Self>() - size_of::<Edit>() + self.edit.memory()
+    }
+}
+
+/// Undo and redo steps, stored as the edits themselves rather than copies of the image.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    /// Memory taken by both stacks.
+    memory: usize,
+}
+
+impl History {
+    /// Applies `edit` to `shapes` and records it, along with the marker counter
+    /// before and after. Anything that could be redone is dropped.
+    pub fn apply(&mut self, shapes: &mut Vec<Shape>, edit: Edit, next_marker: (u32, u32)) {
+        edit.apply(shapes);
+
+        let step = Step { edit, next_marker };
+        self.memory -= self.redo.drain(..).map(|step| step.memory()).sum::<usize>();
+        self.memory += step.memory();
+        self.undo.push_back(step);
+
+        while self.memory > MEMORY_BUDGET && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.memory -= oldest.memory();
+            }
+        }
+    }
+
+    /// Reverts the last edit. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, shapes: &mut Vec<Shape>, next_marker: &mut u32) -> bool {
+        let Some(step) = self.undo.pop_back() else { return false; };
+        step.edit.revert(shapes);
+
+        let (before, after) = step.next_marker;
+        if before != after {
+            *next_marker = before;
+        }
+
+        self.redo.push(step);
+        true
+    }
+
+    /// Applies the last undone edit again. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, shapes: &mut Vec<Shape>, next_marker: &mut u32) -> bool {
+        let Some(step) = self.redo.pop() else { return false; };
+        step.edit.apply(shapes);
+
+        let (before, after) = step.next_marker;
+        if before != after {
+            *next_marker = after;
+        }
+
+        self.undo.push_back(step);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::screenshot::state::{Rect, Style};
+
+    fn redact(x: i32) -> Shape {
+        Shape::Redact { rect: Rect { x, y: 0, w: 10, h: 10 } }
+    }
+
+    /// A pen stroke taking about `bytes` of memory.
+    fn stroke(bytes: usize) -> Shape {
+        Shape::Stroke {
+            points: vec![(0, 0); bytes / size_of::<(i32, i32)>()],
+            color: (255, 0, 0),
+            highlighter: false,
+            style: Style::default(),
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let (mut history, mut shapes, mut marker) = (History::default(), Vec::new(), 1);
+
+        history.apply(&mut shapes, Edit::Add(redact(0)), (1, 1));
+        history.apply(&mut shapes, Edit::Replace(0, redact(0), redact(5)), (1, 1));
+        history.apply(&mut shapes, Edit::Add(redact(10)), (1, 1));
+        history.apply(&mut shapes, Edit::Remove(0, redact(5)), (1, 1));
+        assert_eq!(shapes, vec![redact(10)]);
+
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert_eq!(shapes, vec![redact(5), redact(10)]);
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert_eq!(shapes, vec![redact(0)]);
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert!(shapes.is_empty());
+        assert!(!history.undo(&mut shapes, &mut marker));
+
+        while history.redo(&mut shapes, &mut marker) {}
+        assert_eq!(shapes, vec![redact(10)]);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let (mut history, mut shapes, mut marker) = (History::default(), Vec::new(), 1);
+
+        history.apply(&mut shapes, Edit::Add(redact(0)), (1, 1));
+        history.apply(&mut shapes, Edit::Add(redact(10)), (1, 1));
+        assert!(history.undo(&mut shapes, &mut marker));
+
+        history.apply(&mut shapes, Edit::Add(redact(20)), (1, 1));
+        assert!(!history.redo(&mut shapes, &mut marker));
+        assert_eq!(shapes, vec![redact(0), redact(20)]);
+    }
+
+    #[test]
+    fn marker_counter_follows_only_its_own_edits() {
+        let (mut history, mut shapes, mut marker) = (History::default(), Vec::new(), 3);
+
+        history.apply(&mut shapes, Edit::Add(redact(0)), (1, 2));
+        history.apply(&mut shapes, Edit::Add(redact(10)), (3, 3));
+
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert_eq!(marker, 3);
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert_eq!(marker, 1);
+        assert!(history.redo(&mut shapes, &mut marker));
+        assert_eq!(marker, 2);
+    }
+
+    #[test]
+    fn memory_budget_drops_oldest_edit() {
+        let (mut history, mut shapes, mut marker) = (History::default(), Vec::new(), 1);
+
+        history.apply(&mut shapes, Edit::Add(stroke(MEMORY_BUDGET / 2)), (1, 1));
+        history.apply(&mut shapes, Edit::Add(stroke(MEMORY_BUDGET / 2)), (1, 1));
+        history.apply(&mut shapes, Edit::Add(redact(0)), (1, 1));
+        assert!(history.memory <= MEMORY_BUDGET);
+
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert!(!history.undo(&mut shapes, &mut marker));
+        assert_eq!(shapes.len(), 1);
+    }
+
+    #[test]
+    fn oversized_edit_is_still_undoable() {
+        let (mut history, mut shapes, mut marker) = (History::default(), Vec::new(), 1);
+
+        history.apply(&mut shapes, Edit::Add(stroke(MEMORY_BUDGET * 2)), (1, 1));
+        assert!(history.undo(&mut shapes, &mut marker));
+        assert!(shapes.is_empty());
+    }
+}
@@ -2,31 +2,40 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::mpsc;
 
+use glib::clone;
 use gtk::prelude::*;
 
 mod canvas;
 mod events;
-mod render;
+mod history;
+pub mod render;
+mod selection_editor;
+mod snapping;
 pub mod state;
+mod text_input;
 mod toolbar;
 mod ui;
 
-use self::state::ScreenshotState;
+use self::state::{OverlayMode, ScreenshotState};
 use self::ui::ScreenshotWidgets;
-use crate::action::{AppAction, GlobalAction, ScreenshotAction};
+use crate::action::{AppAction, ScreenshotAction};
 use crate::capture::clipboard;
 use crate::common::cursor;
+use crate::common::timing::PhaseTimer;
+use crate::config::Config;
 use crate::modules::screenshot::canvas::Canvas;
 
-pub fn run(app: &gtk::Application) {
+pub fn run(app: &gtk::Application, config: &Config) {
     let (tx, rx) = mpsc::channel::<AppAction>();
     let app_handle = app.clone();
-    let state = Rc::new(RefCell::new(ScreenshotState::default()));
+    let state = Rc::new(RefCell::new(ScreenshotState::new(config)));
+    let mut timer = PhaseTimer::from_env();
 
     let canvas = Rc::new(
-        Canvas::from_screenshot()
+        Canvas::from_screenshot(config, &mut timer)
             .expect("Failed to create ")
     );
+    timer.mark("canvas");
 
     let widgets = Rc::new(
         ScreenshotWidgets::build(
@@ -37,8 +46,17 @@ pub fn run(app: &gtk::Application) {
         )
     );
 
+    timer.mark("ui");
+
     crate::modules::screenshot::events::init_events(tx, &widgets);
 
+    // Edge detection reads the whole capture, so it waits until the overlay is up
+    let edges_canvas = canvas.clone();
+    after_first_frame(&widgets.window, move || {
+        timer.mark("first frame");
+        edges_canvas.detect_edges();
+    });
+
     glib::idle_add_local(move || {
         while let Ok(action) = rx.try_recv() {
             handle_action(&app_handle, action, &state, &widgets, &canvas);
@@ -47,6 +65,40 @@ pub fn run(app: &gtk::Application) {
     });
 }
 
+/// Runs `callback` once the window has painted its first frame, or right
+/// away if it isn't realized yet.
+fn after_first_frame(window: &gtk::ApplicationWindow, callback: impl FnOnce() + 'static) {
+    let Some(clock) = window.frame_clock() else {
+        callback();
+        return;
+    };
+
+    let callback = RefCell::new(Some(callback));
+    let handler = Rc::new(RefCell::new(None));
+
+    let id = clock.connect_after_paint(clone!(#[strong] handler, move |clock| {
+        if let Some(callback) = callback.take() {
+            callback();
+        }
+        if let Some(id) = handler.take() {
+            clock.disconnect(id);
+        }
+    }));
+    handler.replace(Some(id));
+}
+
+fn export_and_quit(app: &gtk::Application, state: &mut ScreenshotState, canvas: &Canvas) {
+    if !state.begin_export() {
+        return;
+    }
+
+    let buf = canvas.composed(state.shapes(), state.shapes_revision())
+        .and_then(|composed| state.export_selection(&composed))
+        .expect("Failed export");
+    let _ = clipboard::copy_to_clipboard(&buf);
+    app.quit();
+}
+
 fn handle_action(
     app: &gtk::Application,
     action: AppAction,
@@ -58,15 +110,36 @@ fn handle_action(
     let mut need_redraw = false;
 
     match action {
-        AppAction::Global(GlobalAction::Quit) => app.quit(),
+        AppAction::Screenshot(_) if s.mode() == OverlayMode::Exporting => {}
         AppAction::Screenshot(sub_action) => {
             match sub_action {
-                ScreenshotAction::SetTool(tool) => s.set_tool(tool),
+                ScreenshotAction::SetTool(tool) => {
+                    s.finish_text();
+                    s.set_tool(tool);
+                }
                 ScreenshotAction::SetColor(red, green, blue) => s.set_color((red, green, blue)),
+                ScreenshotAction::SetStrokeWidth(width) => s.set_stroke_width(width),
+                ScreenshotAction::SetDash(dash) => s.set_dash(dash),
+                ScreenshotAction::SetOpacity(opacity) => s.set_opacity(opacity),
+                ScreenshotAction::SetContrast(contrast) => s.set_contrast(contrast),
+                ScreenshotAction::SetArrowhead(arrowhead) => s.set_arrowhead(arrowhead),
+                ScreenshotAction::SetFillColor(red, green, blue) => s.set_fill_color((red, green, blue)),
+                ScreenshotAction::SetFillOpacity(opacity) => s.set_fill_opacity(opacity),
+                ScreenshotAction::SetPixelBlock(block) => s.set_pixel_block(block),
+                ScreenshotAction::SetSpotlightEllipse(ellipse) => s.set_spotlight_ellipse(ellipse),
+                ScreenshotAction::SetSpotlightOpacity(opacity) => s.set_spotlight_opacity(opacity),
+                ScreenshotAction::SetMagnifyZoom(zoom) => s.set_magnify_zoom(zoom),
+                ScreenshotAction::SetMagnifyConnector(connector) => s.set_magnify_connector(connector),
                 ScreenshotAction::ToogleMode => {
-                    if s.selection().is_active() && !s.is_paused() {
-                        s.toogle_pause();
-                        widgets.toolbar.widget().set_opacity(1.0);
+                    if s.enter_editor() {
+                        widgets.toolbar.set_shown(true);
+                    }
+                }
+                ScreenshotAction::Cancel => {
+                    if s.cancel() {
+                        widgets.toolbar.set_shown(s.is_editing());
+                    } else {
+                        app.quit();
                     }
                 }
                 ScreenshotAction::MouseMove(x, y) => {
@@ -77,47 +150,97 @@ fn handle_action(
                             s.mouse_pos(),
                             &widgets.drawing_area,
                         );
-                        if s.is_paused() {
+                        if s.is_editing() {
                             widgets.toolbar.update_position(&s.selection().rect());
                         }
                     }
                 }
-                ScreenshotAction::DragBegin(x, y) => { 
-                    s.begin_drag(x, y);
-                    canvas.save_shapshot(&*s);
+                ScreenshotAction::DragBegin(x, y, modifiers) => { 
+                    let rect = *s.selection().rect();
+                    let label = render::selection_label_rect(&rect);
+
+                    if s.finish_text() {
+                        // Clicking away from a text box only finishes it
+                    } else if s.is_editing() && !rect.is_empty() && label.contains((x, y)) {
+                        widgets.selection_editor.open(&rect, (x, y));
+                    } else {
+                        s.begin_drag(x, y, modifiers);
+                    }
                 },
-                ScreenshotAction::DragUpdate(x, y) => {
-                    s.update_drag(x, y);
+                ScreenshotAction::DragUpdate(x, y, modifiers) => {
+                    let edges = (s.snaps_edges() && !modifiers.alt).then(|| canvas.edges()).flatten();
+                    s.update_drag(x, y, modifiers, edges);
                     widgets.toolbar.update_position(&s.selection().rect());
                 }
                 ScreenshotAction::DragEnd => {
-                    if let Some(shape) = s.current_shape() {
-                        canvas.apply_shape(shape);
+                    if s.end_drag() && s.is_quick_capture_done() {
+                        export_and_quit(app, &mut s, canvas);
                     }
-                    s.end_drag();
+                }
+                ScreenshotAction::Nudge(dx, dy) => {
+                    s.nudge(dx, dy, &canvas.surface.borrow().bounds());
+                    widgets.toolbar.update_position(s.selection().rect());
+                }
+                ScreenshotAction::NudgeEdge(zone, dx, dy) => {
+                    s.nudge_handle(zone, dx, dy, &canvas.surface.borrow().bounds());
+                    widgets.toolbar.update_position(s.selection().rect());
+                }
+                ScreenshotAction::SetSelection(rect) => {
+                    s.set_selection_rect(rect);
 
-                    if !s.is_paused() {
-                        let buf = s.export_selection(&canvas.surface.borrow())
-                            .expect("Failed export");
-                        let _ = clipboard::copy_to_clipboard(&buf);
+                    // Typed geometry goes to the editor instead of straight to the clipboard
+                    if s.enter_editor() {
+                        widgets.toolbar.set_shown(true);
+                    }
+                    widgets.toolbar.update_position(s.selection().rect());
+                }
+                ScreenshotAction::OpenSelectionEditor => {
+                    widgets.selection_editor.open(s.selection().rect(), s.mouse_pos());
+                }
+                ScreenshotAction::CycleHandle(forward) => s.cycle_handle(forward),
+                ScreenshotAction::SetPreset(preset) => {
+                    s.apply_preset(preset, &canvas.surface.borrow().bounds());
+                    widgets.toolbar.update_position(s.selection().rect());
+                }
+                ScreenshotAction::SetSelectionMode(mode) => s.set_selection_mode(mode),
+                ScreenshotAction::ClosePolygon => {
+                    if s.close_polygon() {
+                        widgets.toolbar.update_position(s.selection().rect());
 
-                        app.quit();
+                        if s.is_quick_capture_done() {
+                            export_and_quit(app, &mut s, canvas);
+                        }
                     }
                 }
+                ScreenshotAction::TextInput(text) => s.insert_text(&text),
+                ScreenshotAction::TextBackspace => s.delete_text_backward(),
+                ScreenshotAction::TextDelete => s.delete_text_forward(),
+                ScreenshotAction::TextMoveCaret(forward) => s.move_text_caret(forward),
+                ScreenshotAction::TextCommit => { s.finish_text(); }
+                ScreenshotAction::SetTextFont(font) => s.set_text_font(font),
+                ScreenshotAction::SetTextBackground(background) => s.set_text_background(background),
                 ScreenshotAction::Save => {
-                    let buf = s.export_selection(&canvas.surface.borrow())
-                        .expect("Failed export");
-                    let _ = clipboard::copy_to_clipboard(&buf);
-                    app.quit();
+                    s.finish_text();
+                    export_and_quit(app, &mut s, canvas);
                 }
 
-                ScreenshotAction::Undo => { canvas.restore_snapshot() }
+                ScreenshotAction::Undo => s.undo(),
+                ScreenshotAction::Redo => s.redo(),
+                ScreenshotAction::DeleteShape => s.delete_selected_shape(),
+                ScreenshotAction::ResetMarkers => s.reset_markers(),
 
             }
             need_redraw = true;
         }
     }
 
+    if let Some(size) = s.editing_text().and_then(render::measure_text) {
+        s.set_text_size(size);
+    }
+
+    let caret = s.editing_text().map(|text| text.bounds());
+    widgets.text_input.set_active(caret.is_some(), caret);
+
     if need_redraw {
         widgets.drawing_area.queue_draw();
     }
@@ -1,6 +1,13 @@
-use cairo::{Context, ImageSurface};
+use std::sync::OnceLock;
 
-use crate::modules::screenshot::state::{Rect, Shape};
+use cairo::Context;
+use pangocairo::pango;
+use pangocairo::prelude::*;
+
+use crate::common::tiled_surface::TiledSurface;
+use crate::modules::screenshot::state::{
+    Arrowhead, Contrast, Dash, Fill, Rect, Region, SelectionHitZone, Shape, Spotlight, Style,
+};
 
 fn set_color(cr: &Context, color: (u8, u8, u8), alpha: f64) {
     cr.set_source_rgba(
@@ -11,31 +18,404 @@ fn set_color(cr: &Context, color: (u8, u8, u8), alpha: f64) {
     );
 }
 
+/// Dims everything outside `holes`. Overlapping holes stay clear,
+/// unlike an even-odd fill.
+pub fn draw_dim(cr: &Context, size: (f64, f64), holes: &[Region]) {
+    cr.push_group();
+
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+    cr.paint().expect("Cairo paint failed");
+
+    cr.set_operator(cairo::Operator::Clear);
+    for region in holes {
+        trace_region(cr, region, 0.0);
+        cr.fill().expect("Cairo fill failed");
+    }
+
+    cr.pop_group_to_source().expect("Cairo group failed");
+    cr.rectangle(0.0, 0.0, size.0, size.1);
+    cr.fill().expect("Cairo fill failed");
+}
+
+/// Adds the outline of `region` to the current path. `inset` keeps
+/// hairlines of rectangles on pixel centers.
+fn trace_region(cr: &Context, region: &Region, inset: f64) {
+    match region {
+        Region::Rect(rect) => {
+            let (x, y, w, h) = rect.as_f64();
+            cr.rectangle(x + inset, y + inset, w - inset * 2.0, h - inset * 2.0);
+        }
+        Region::Outline(points) => {
+            cr.new_sub_path();
+            for (x, y) in points {
+                cr.line_to(*x, *y);
+            }
+            cr.close_path();
+        }
+    }
+}
+
+/// Darkens `region` except inside `spotlights`, the same way [`draw_dim`] clears the selection.
+pub fn draw_spotlight(cr: &Context, region: &Region, spotlights: &[Spotlight], opacity: u8) {
+    if spotlights.is_empty() {
+        return;
+    }
+
+    cr.save().expect("Failed to save state");
+    trace_region(cr, region, 0.0);
+    cr.clip();
+    cr.push_group();
+
+    cr.set_source_rgba(0.0, 0.0, 0.0, opacity as f64 / 100.0);
+    cr.paint().expect("Cairo paint failed");
+
+    cr.set_operator(cairo::Operator::Clear);
+    for spotlight in spotlights {
+        let (x, y, w, h) = spotlight.rect.as_f64();
+
+        if spotlight.ellipse {
+            cr.save().expect("Failed to save state");
+            cr.translate(x + w / 2.0, y + h / 2.0);
+            cr.scale(w / 2.0, h / 2.0);
+            cr.arc(0.0, 0.0, 1.0, 0.0, std::f64::consts::TAU);
+            cr.restore().expect("Failed to restore state");
+        } else {
+            cr.rectangle(x, y, w, h);
+        }
+        cr.fill().expect("Cairo fill failed");
+    }
+
+    cr.pop_group_to_source().expect("Cairo group failed");
+    cr.paint().expect("Cairo paint failed");
+    cr.restore().expect("Failed to restore state");
+}
+
 pub fn draw_selection(
     cr: &Context,
-    rect: &Rect,
+    region: &Region,
 ) {
-    let (x, y, w, h) = rect.as_f64();
+    cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    cr.set_line_width(1.0);
+    trace_region(cr, region, 0.5);
+    cr.stroke().expect("Cairo stroke failed");
+}
 
-    cr.rectangle(x, y, w, h);
-    cr.set_fill_rule(cairo::FillRule::EvenOdd);
+/// Draws the sides of a polygon that is still being placed, plus the
+/// rubber band to the pointer. The first point is circled as the close target.
+pub fn draw_polygon_preview(cr: &Context, points: &[(i32, i32)], mouse: (i32, i32)) {
+    let Some(first) = points.first() else { return; };
+
+    for (x, y) in points.iter().chain(std::iter::once(&mouse)) {
+        cr.line_to(*x as f64 + 0.5, *y as f64 + 0.5);
+    }
+
+    cr.set_line_width(1.0);
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+    cr.stroke_preserve().expect("Cairo stroke failed");
+    cr.set_dash(&[4.0, 4.0], 0.0);
+    cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    cr.stroke().expect("Cairo stroke failed");
+    cr.set_dash(&[], 0.0);
 
+    cr.arc(first.0 as f64 + 0.5, first.1 as f64 + 0.5, 5.0, 0.0, std::f64::consts::TAU);
     cr.fill().expect("Cairo fill failed");
+}
+
+const LABEL_FONT_SIZE: f64 = 12.0;
+const LABEL_PADDING: f64 = 5.0;
+
+fn selection_label_text(rect: &Rect) -> String {
+    format!("{}×{}  {},{}", rect.w, rect.h, rect.x, rect.y)
+}
+
+fn set_label_font(cr: &Context) {
+    cr.select_font_face("monospace", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+    cr.set_font_size(LABEL_FONT_SIZE);
+}
+
+/// Width of one character of the monospace label font, measured once.
+fn label_advance() -> f64 {
+    static ADVANCE: OnceLock<f64> = OnceLock::new();
+
+    *ADVANCE.get_or_init(|| {
+        cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+            .and_then(|surface| Context::new(&surface))
+            .and_then(|cr| {
+                set_label_font(&cr);
+                cr.text_extents("0")
+            })
+            .map(|extents| extents.x_advance())
+            .unwrap_or(LABEL_FONT_SIZE * 0.6)
+    })
+}
+
+/// Area covered by the size label of `rect`, used both for drawing and hit-testing.
+/// The label sits above the selection, or inside it when there is no room.
+pub fn selection_label_rect(rect: &Rect) -> Rect {
+    let text_width = selection_label_text(rect).chars().count() as f64 * label_advance();
+
+    let w = (text_width + LABEL_PADDING * 2.0).ceil() as i32;
+    let h = (LABEL_FONT_SIZE + LABEL_PADDING * 2.0).ceil() as i32;
+    let above = rect.y - h - 4;
 
-    cr.set_operator(cairo::Operator::Over);
+    if above >= 0 {
+        Rect { x: rect.x, y: above, w, h }
+    } else {
+        Rect { x: rect.x + 4, y: rect.y + 4, w, h }
+    }
+}
+
+/// Draws the selection size and position in physical pixels.
+pub fn draw_selection_label(cr: &Context, rect: &Rect) {
+    let label = selection_label_rect(rect);
+    let (x, y, w, h) = label.as_f64();
+
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.75);
+    cr.rectangle(x, y, w, h);
+    cr.fill().expect("Cairo fill failed");
 
     cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    set_label_font(cr);
+    cr.move_to(x + LABEL_PADDING, y + LABEL_PADDING + LABEL_FONT_SIZE * 0.85);
+    cr.show_text(&selection_label_text(rect)).expect("Cairo text failed");
+}
+
+const HANDLE_SIZE: f64 = 8.0;
+
+/// Draws the grab handles of a finalized selection, at the corners and edge
+/// midpoints `get_cursor_zone` reacts to. The handle moved by the arrow keys is highlighted.
+pub fn draw_handles(cr: &Context, rect: &Rect, active: SelectionHitZone) {
+    let (x, y, w, h) = rect.as_f64();
+    let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+
+    let handles = [
+        (SelectionHitZone::NW, x, y), (SelectionHitZone::N, cx, y), (SelectionHitZone::NE, x + w, y),
+        (SelectionHitZone::W, x, cy), (SelectionHitZone::E, x + w, cy),
+        (SelectionHitZone::SW, x, y + h), (SelectionHitZone::S, cx, y + h), (SelectionHitZone::SE, x + w, y + h),
+    ];
+
     cr.set_line_width(1.0);
-    cr.rectangle(x + 0.5, y + 0.5, w - 1.0, h - 1.0);
+
+    for (zone, hx, hy) in handles {
+        let (left, top) = ((hx - HANDLE_SIZE / 2.0).round() + 0.5, (hy - HANDLE_SIZE / 2.0).round() + 0.5);
+        cr.rectangle(left, top, HANDLE_SIZE, HANDLE_SIZE);
+
+        if zone == active {
+            cr.set_source_rgba(0.2, 0.6, 1.0, 1.0);
+        } else {
+            cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+        }
+        cr.fill_preserve().expect("Cairo fill failed");
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+        cr.stroke().expect("Cairo stroke failed");
+    }
+}
+
+/// Bakes `shape` permanently into `surface`.
+pub fn bake_shape(surface: &mut TiledSurface, shape: &Shape) -> anyhow::Result<()> {
+    let Some(area) = shape.bounds().intersection(&surface.bounds()) else { return Ok(()); };
+
+    // Shapes are drawn onto a patch that is written back afterwards,
+    // so blur never samples tiles that were already modified.
+    let patch = surface.region(&area)?;
+    {
+        let cr = Context::new(&patch)?;
+        cr.translate(-area.x as f64, -area.y as f64);
+        draw_shape(surface, &cr, shape);
+    }
+    surface.write_region(&patch, area.x, area.y)
+}
+
+/// Copies `source` and draws `shapes` over it in order. Effects such as blur
+/// sample `sampled`, never the shapes below them.
+pub fn compose(source: &TiledSurface, sampled: &TiledSurface, shapes: &[Shape]) -> anyhow::Result<TiledSurface> {
+    let mut composed = source.clone();
+
+    for shape in shapes {
+        let Some(area) = shape.bounds().intersection(&composed.bounds()) else { continue; };
+
+        let patch = composed.region(&area)?;
+        {
+            let cr = Context::new(&patch)?;
+            cr.translate(-area.x as f64, -area.y as f64);
+            draw_shape(sampled, &cr, shape);
+        }
+        composed.write_region(&patch, area.x, area.y)?;
+    }
+
+    Ok(composed)
+}
+
+/// Copy of `source` with only the redactions in `shapes` drawn, or `None`
+/// without any. Effects that read the capture sample this, so a blur or
+/// magnifier over a redaction can't bring back what it covers.
+pub fn redact(source: &TiledSurface, shapes: &[Shape]) -> anyhow::Result<Option<TiledSurface>> {
+    let redactions: Vec<Shape> = shapes.iter().filter(|shape| is_redaction(shape)).cloned().collect();
+
+    if redactions.is_empty() {
+        return Ok(None);
+    }
+
+    compose(source, source, &redactions).map(Some)
+}
+
+/// Redraws `area` of `target` from `source` and every shape touching it, in order.
+pub fn redraw(
+    target: &mut TiledSurface,
+    source: &TiledSurface,
+    sampled: &TiledSurface,
+    shapes: &[Shape],
+    area: &Rect,
+) -> anyhow::Result<()> {
+    let Some(area) = area.intersection(&target.bounds()) else { return Ok(()); };
+
+    let patch = source.region(&area)?;
+    {
+        let cr = Context::new(&patch)?;
+        cr.translate(-area.x as f64, -area.y as f64);
+
+        for shape in shapes.iter().filter(|shape| shape.bounds().intersection(&area).is_some()) {
+            draw_shape(sampled, &cr, shape);
+        }
+    }
+    target.write_region(&patch, area.x, area.y)
+}
+
+/// Shapes that differ between `before` and `after`, from both lists. Shapes
+/// in the common prefix and suffix keep their place in the draw order.
+pub fn changed_shapes<'a>(before: &'a [Shape], after: &'a [Shape]) -> Vec<&'a Shape> {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let (before, after) = (&before[prefix..], &after[prefix..]);
+    let suffix = before.iter().rev().zip(after.iter().rev()).take_while(|(a, b)| a == b).count();
+
+    before[..before.len() - suffix]
+        .iter()
+        .chain(&after[..after.len() - suffix])
+        .collect()
+}
+
+pub fn is_redaction(shape: &Shape) -> bool {
+    matches!(shape, Shape::Redact { .. })
+}
+
+/// Whether drawing `shape` reads pixels of the capture.
+pub fn samples_capture(shape: &Shape) -> bool {
+    matches!(shape, Shape::Blur { .. } | Shape::Pixelate { .. } | Shape::Magnify { .. })
+}
+
+/// Outlines the annotation picked with the select tool, with handles if it can be resized.
+pub fn draw_shape_frame(cr: &Context, frame: &Rect, resizable: bool) {
+    let (x, y, w, h) = frame.as_f64();
+
+    cr.set_line_width(1.0);
+    cr.set_dash(&[4.0, 4.0], 0.0);
+    cr.rectangle(x.round() + 0.5, y.round() + 0.5, w, h);
+    cr.set_source_rgba(0.2, 0.6, 1.0, 1.0);
     cr.stroke().expect("Cairo stroke failed");
+    cr.set_dash(&[], 0.0);
+
+    if resizable {
+        draw_handles(cr, frame, SelectionHitZone::Outside);
+    }
 }
 
-pub fn draw_shape(surface: &ImageSurface, cr: &Context, shape: &Shape) {
+pub fn draw_shape(surface: &TiledSurface, cr: &Context, shape: &Shape) {
     match shape {
-        Shape::Arrow { from, to, color } => draw_arrow(cr, *from, *to, *color),
-        Shape::Rectangle { rect, color } => draw_rectangle(cr, rect, *color),
+        Shape::Arrow { from, to, color, style } => draw_arrow(cr, *from, *to, *color, style),
+        Shape::Rectangle { rect, color, fill, style } => draw_rectangle(cr, rect, *color, *fill, style),
+        Shape::Ellipse { rect, color, fill, style } => draw_ellipse(cr, rect, *color, *fill, style),
+        Shape::Line { from, to, color, style } => draw_line(cr, *from, *to, *color, style),
+        Shape::Marker { center, number, color, style } => draw_marker(cr, *center, *number, *color, style),
         Shape::Blur { rect } => draw_blur(surface, cr, rect),
+        Shape::Pixelate { rect, block } => draw_pixelate(surface, cr, rect, *block),
+        Shape::Redact { rect } => draw_redact(cr, rect),
+        Shape::Magnify { source, target, color, connector, style } => {
+            draw_magnify(surface, cr, source, target, *color, *connector, style)
+        }
+        // Drawn over the whole selection by `draw_spotlight` instead
+        Shape::Spotlight { .. } => {}
+        Shape::Text { pos, text, font, color, background, style, .. } => {
+            draw_text(cr, *pos, text, font, *color, *background, style)
+        }
+        Shape::Stroke { points, color, highlighter, style } => {
+            draw_stroke(cr, points, *color, *highlighter, style)
+        }
+    }
+}
+
+/// Line width added around a shape by its contrast outline.
+pub const OUTLINE_GROW: f64 = 4.0;
+/// How far the drop shadow is moved down and to the right.
+pub const SHADOW_OFFSET: f64 = 3.0;
+
+fn luminance(color: (u8, u8, u8)) -> f64 {
+    0.299 * color.0 as f64 + 0.587 * color.1 as f64 + 0.114 * color.2 as f64
+}
+
+/// Black for light colours and white for dark ones.
+fn contrast_color(color: (u8, u8, u8)) -> (u8, u8, u8) {
+    if luminance(color) < 128.0 { (255, 255, 255) } else { (0, 0, 0) }
+}
+
+/// One of the layers a styled shape is drawn in, see [`draw_styled`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Outline,
+    Shadow,
+    Main,
+}
+
+/// Draws a shape with `style` by calling `draw` for each pass it needs, with
+/// the colour and line width of that pass: the outline or shadow first, then
+/// the shape itself. The passes are composited together at the style's
+/// opacity, so they don't show through each other.
+fn draw_styled(
+    cr: &Context,
+    style: &Style,
+    color: (u8, u8, u8),
+    operator: cairo::Operator,
+    draw: impl Fn(&Context, Pass, (u8, u8, u8), f64),
+) {
+    let width = style.width as f64;
+
+    cr.save().expect("Failed to save state");
+    cr.push_group();
+
+    match style.dash {
+        Dash::Solid => cr.set_dash(&[], 0.0),
+        Dash::Dashed => cr.set_dash(&[width * 3.0, width * 2.0], 0.0),
+        Dash::Dotted => {
+            cr.set_line_cap(cairo::LineCap::Round);
+            cr.set_dash(&[0.0, width * 2.0], 0.0);
+        }
+    }
+
+    match style.contrast {
+        Contrast::None => {}
+        Contrast::Outline => draw(cr, Pass::Outline, contrast_color(color), width + OUTLINE_GROW),
+        Contrast::Shadow => {
+            cr.push_group();
+            cr.translate(SHADOW_OFFSET, SHADOW_OFFSET);
+            draw(cr, Pass::Shadow, (0, 0, 0), width);
+            cr.pop_group_to_source().expect("Cairo group failed");
+            cr.paint_with_alpha(0.45).expect("Cairo paint failed");
+        }
     }
+
+    draw(cr, Pass::Main, color, width);
+
+    cr.pop_group_to_source().expect("Cairo group failed");
+    cr.set_operator(operator);
+    cr.paint_with_alpha(style.opacity as f64 / 100.0).expect("Cairo paint failed");
+    cr.restore().expect("Failed to restore state");
+}
+
+const ARROWHEAD_ANGLE: f64 = 0.5;
+
+/// Length of the arrowhead sides, growing with the line width.
+pub fn arrowhead_length(style: &Style) -> f64 {
+    style.width as f64 * 4.0 + 2.0
 }
 
 pub fn draw_arrow(
@@ -43,56 +423,150 @@ pub fn draw_arrow(
     from: (i32, i32),
     to: (i32, i32),
     color: (u8, u8, u8),
+    style: &Style,
 ) {
-    
     let (x1, y1) = (from.0 as f64, from.1 as f64);
     let (x2, y2) = (to.0 as f64, to.1 as f64);
 
-    set_color(cr, color, 1.0);
-    
-    cr.set_line_width(2.5);
-    cr.move_to(x1, y1);
-    cr.line_to(x2, y2);
-    cr.stroke().expect("Cairo stroke failed");
-
+    let length = arrowhead_length(style);
     let angle = (y2 - y1).atan2(x2 - x1);
-    let arrow_len = 14.0;
-    let arrow_ang = 0.5;
+    let (dx, dy) = (angle.cos(), angle.sin());
 
-    cr.move_to(x2, y2);
-    cr.line_to(
-        x2 - arrow_len * (angle - arrow_ang).cos(),
-        y2 - arrow_len * (angle - arrow_ang).sin()
-    );
-    cr.line_to(
-        x2 - arrow_len * (angle + arrow_ang).cos(),
-        y2 - arrow_len * (angle + arrow_ang).sin()
+    // End the shaft at the base of filled heads, so wide lines don't stick out beside the tip
+    let inset = length * ARROWHEAD_ANGLE.cos();
+    let (start, end) = match style.arrowhead {
+        Arrowhead::Filled => ((x1, y1), (x2 - dx * inset, y2 - dy * inset)),
+        Arrowhead::Double => ((x1 + dx * inset, y1 + dy * inset), (x2 - dx * inset, y2 - dy * inset)),
+        Arrowhead::Open | Arrowhead::None => ((x1, y1), (x2, y2)),
+    };
+
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, width| {
+        set_color(cr, color, 1.0);
+
+        cr.set_line_width(width);
+        cr.move_to(start.0, start.1);
+        cr.line_to(end.0, end.1);
+        cr.stroke().expect("Cairo stroke failed");
+
+        cr.set_dash(&[], 0.0);
+        let halo = (pass == Pass::Outline).then_some(OUTLINE_GROW);
+
+        match style.arrowhead {
+            Arrowhead::Filled => draw_arrowhead(cr, (x2, y2), angle, length, None, halo),
+            Arrowhead::Double => {
+                draw_arrowhead(cr, (x2, y2), angle, length, None, halo);
+                draw_arrowhead(cr, (x1, y1), angle + std::f64::consts::PI, length, None, halo);
+            }
+            Arrowhead::Open => draw_arrowhead(cr, (x2, y2), angle, length, Some(width), None),
+            Arrowhead::None => {}
+        }
+    });
+}
+
+/// Draws a head pointing along `angle` with its tip at `tip`. `open` gives the
+/// line width of an unfilled head; `halo` widens a filled head for the outline pass.
+fn draw_arrowhead(cr: &Context, tip: (f64, f64), angle: f64, length: f64, open: Option<f64>, halo: Option<f64>) {
+    let side = |offset: f64| (
+        tip.0 - length * (angle + offset).cos(),
+        tip.1 - length * (angle + offset).sin(),
     );
+    let (left, right) = (side(-ARROWHEAD_ANGLE), side(ARROWHEAD_ANGLE));
 
-    cr.close_path();
-    cr.fill().expect("Cairo stroke failed");
+    cr.move_to(left.0, left.1);
+    cr.line_to(tip.0, tip.1);
+    cr.line_to(right.0, right.1);
+
+    if let Some(width) = open {
+        cr.set_line_width(width);
+        cr.set_line_cap(cairo::LineCap::Round);
+        cr.set_line_join(cairo::LineJoin::Round);
+        cr.stroke().expect("Cairo stroke failed");
+        return;
+    }
 
+    cr.close_path();
+    if let Some(halo) = halo {
+        cr.set_line_width(halo);
+        cr.set_line_join(cairo::LineJoin::Round);
+        cr.stroke_preserve().expect("Cairo stroke failed");
+    }
+    cr.fill().expect("Cairo fill failed");
 }
 
 pub fn draw_rectangle(
     cr: &Context,
     rect: &Rect,
-    color: (u8, u8, u8)
+    color: (u8, u8, u8),
+    fill: Option<Fill>,
+    style: &Style,
 ) {
     let (x, y, w, h) = rect.as_f64();
 
-    set_color(cr, color, 1.0);
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, width| {
+        cr.rectangle(x, y, w, h);
+        fill_and_stroke(cr, color, fill.filter(|_| pass == Pass::Main), width);
+    });
+}
 
-    cr.set_line_width(2.0);
-    cr.rectangle(x, y, w, h);
+pub fn draw_ellipse(
+    cr: &Context,
+    rect: &Rect,
+    color: (u8, u8, u8),
+    fill: Option<Fill>,
+    style: &Style,
+) {
+    let (x, y, w, h) = rect.as_f64();
+
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, width| {
+        // Scale a unit circle; the transform is undone before stroking so the line keeps its width
+        cr.save().expect("Failed to save state");
+        cr.translate(x + w / 2.0, y + h / 2.0);
+        cr.scale(w / 2.0, h / 2.0);
+        cr.new_sub_path();
+        cr.arc(0.0, 0.0, 1.0, 0.0, std::f64::consts::TAU);
+        cr.restore().expect("Failed to restore state");
+
+        fill_and_stroke(cr, color, fill.filter(|_| pass == Pass::Main), width);
+    });
+}
+
+/// Fills the current path with `fill`, if any, then outlines it in `color`.
+fn fill_and_stroke(cr: &Context, color: (u8, u8, u8), fill: Option<Fill>, width: f64) {
+    if let Some(fill) = fill {
+        set_color(cr, fill.color, fill.opacity as f64 / 100.0);
+        cr.fill_preserve().expect("Cairo fill failed");
+    }
+
+    set_color(cr, color, 1.0);
+    cr.set_line_width(width);
     cr.stroke().expect("Cairo stroke failed");
 }
 
-pub fn draw_blur(surface: &ImageSurface, cr: &Context, rect: &Rect) {
+pub fn draw_line(
+    cr: &Context,
+    from: (i32, i32),
+    to: (i32, i32),
+    color: (u8, u8, u8),
+    style: &Style,
+) {
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, _, color, width| {
+        set_color(cr, color, 1.0);
+
+        cr.set_line_width(width);
+        cr.set_line_cap(cairo::LineCap::Round);
+        cr.move_to(from.0 as f64, from.1 as f64);
+        cr.line_to(to.0 as f64, to.1 as f64);
+        cr.stroke().expect("Cairo stroke failed");
+    });
+}
+
+pub fn draw_blur(surface: &TiledSurface, cr: &Context, rect: &Rect) {
     let (x, y, w, h) = rect.as_f64();
-    
+
+    let Ok(source) = surface.region(rect) else { return; };
+
     let blurred_region = match crate::common::cairo_blur::blur_image_surface(
-        surface, x, y, rect.w, rect.h, 10
+        &source, 0.0, 0.0, rect.w, rect.h, 10
     ) {
         Ok(s) => s,
         Err(_) => return,
@@ -107,3 +581,394 @@ pub fn draw_blur(surface: &ImageSurface, cr: &Context, rect: &Rect) {
     
     cr.restore().expect("Failed to restore state");
 }
+
+pub fn stroke_width(style: &Style, highlighter: bool) -> f64 {
+    if highlighter { style.width as f64 * 6.0 } else { style.width as f64 }
+}
+
+/// Draws a freehand stroke as a Catmull-Rom spline through `points`, which
+/// smooths out the jitter of individual motion events. The highlighter
+/// multiplies with what is below, so dark text stays readable through it.
+pub fn draw_stroke(cr: &Context, points: &[(i32, i32)], color: (u8, u8, u8), highlighter: bool, style: &Style) {
+    let Some(&first) = points.first() else { return; };
+    let point = |i: usize| {
+        let (x, y) = points[i.min(points.len() - 1)];
+        (x as f64, y as f64)
+    };
+
+    let operator = if highlighter { cairo::Operator::Multiply } else { cairo::Operator::Over };
+    let extra = stroke_width(style, highlighter) - style.width as f64;
+
+    draw_styled(cr, style, color, operator, |cr, _, color, width| {
+        cr.move_to(first.0 as f64, first.1 as f64);
+        for i in 0..points.len() - 1 {
+            let (p0, p1, p2, p3) = (point(i.saturating_sub(1)), point(i), point(i + 1), point(i + 2));
+
+            cr.curve_to(
+                p1.0 + (p2.0 - p0.0) / 6.0, p1.1 + (p2.1 - p0.1) / 6.0,
+                p2.0 - (p3.0 - p1.0) / 6.0, p2.1 - (p3.1 - p1.1) / 6.0,
+                p2.0, p2.1,
+            );
+        }
+
+        set_color(cr, color, if highlighter { 0.6 } else { 1.0 });
+        cr.set_line_width(width + extra);
+        cr.set_line_cap(cairo::LineCap::Round);
+        cr.set_line_join(cairo::LineJoin::Round);
+        cr.stroke().expect("Cairo stroke failed");
+    });
+}
+
+pub const MARKER_RADIUS: f64 = 14.0;
+
+/// Draws a filled circle with the step `number` centered in it.
+pub fn draw_marker(cr: &Context, center: (i32, i32), number: u32, color: (u8, u8, u8), style: &Style) {
+    let (cx, cy) = (center.0 as f64, center.1 as f64);
+
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, _| {
+        cr.set_dash(&[], 0.0);
+        cr.new_sub_path();
+        cr.arc(cx, cy, MARKER_RADIUS, 0.0, std::f64::consts::TAU);
+        set_color(cr, color, 1.0);
+
+        match pass {
+            Pass::Outline => {
+                cr.set_line_width(2.0 + OUTLINE_GROW);
+                cr.stroke().expect("Cairo stroke failed");
+                return;
+            }
+            Pass::Shadow => {
+                cr.fill().expect("Cairo fill failed");
+                return;
+            }
+            Pass::Main => {}
+        }
+
+        cr.fill_preserve().expect("Cairo fill failed");
+        cr.set_source_rgba(1.0, 1.0, 1.0, 0.9);
+        cr.set_line_width(2.0);
+        cr.stroke().expect("Cairo stroke failed");
+
+        if luminance(color) < 160.0 {
+            cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+        } else {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+        }
+
+        let text = number.to_string();
+        cr.select_font_face("sans-serif", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+        cr.set_font_size(if number < 100 { 15.0 } else { 11.0 });
+
+        if let Ok(extents) = cr.text_extents(&text) {
+            cr.move_to(
+                cx - extents.width() / 2.0 - extents.x_bearing(),
+                cy - extents.height() / 2.0 - extents.y_bearing(),
+            );
+            cr.show_text(&text).expect("Cairo text failed");
+        }
+    });
+}
+
+const TEXT_PADDING: f64 = 6.0;
+
+fn text_layout(context: &pango::Context, text: &str, font: &str) -> pango::Layout {
+    let layout = pango::Layout::new(context);
+    layout.set_font_description(Some(&pango::FontDescription::from_string(font)));
+    layout.set_text(text);
+    layout
+}
+
+/// Pixel size of a text annotation as laid out by Pango. An empty text still
+/// has the height of one line, so the caret has a place.
+pub fn measure_text(shape: &Shape) -> Option<(i32, i32)> {
+    let Shape::Text { text, font, .. } = shape else { return None; };
+    let context = pangocairo::FontMap::default().create_context();
+
+    Some(text_layout(&context, text, font).pixel_size())
+}
+
+/// Box covered by a text annotation of the measured `size`, including the
+/// padding of its background.
+pub fn text_rect(pos: (i32, i32), size: (i32, i32)) -> Rect {
+    let padding = TEXT_PADDING as i32;
+
+    Rect { x: pos.0 - padding, y: pos.1 - padding, w: size.0 + padding * 2, h: size.1 + padding * 2 }
+}
+
+pub fn draw_text(
+    cr: &Context,
+    pos: (i32, i32),
+    text: &str,
+    font: &str,
+    color: (u8, u8, u8),
+    background: bool,
+    style: &Style,
+) {
+    let layout = text_layout(&pangocairo::functions::create_context(cr), text, font);
+
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, _| {
+        cr.set_dash(&[], 0.0);
+
+        if background && pass == Pass::Main {
+            let (w, h) = layout.pixel_size();
+
+            // Dark text gets a light box and the other way round
+            if luminance(color) < 128.0 {
+                cr.set_source_rgba(1.0, 1.0, 1.0, 0.85);
+            } else {
+                cr.set_source_rgba(0.0, 0.0, 0.0, 0.7);
+            }
+            cr.rectangle(
+                pos.0 as f64 - TEXT_PADDING,
+                pos.1 as f64 - TEXT_PADDING,
+                w as f64 + TEXT_PADDING * 2.0,
+                h as f64 + TEXT_PADDING * 2.0,
+            );
+            cr.fill().expect("Cairo fill failed");
+        }
+
+        set_color(cr, color, 1.0);
+        cr.move_to(pos.0 as f64, pos.1 as f64);
+
+        if pass == Pass::Outline {
+            pangocairo::functions::layout_path(cr, &layout);
+            cr.set_line_width(OUTLINE_GROW);
+            cr.set_line_join(cairo::LineJoin::Round);
+            cr.stroke().expect("Cairo stroke failed");
+        } else {
+            pangocairo::functions::show_layout(cr, &layout);
+        }
+    });
+}
+
+/// Frames the text box being edited and draws the caret at byte offset `caret`.
+pub fn draw_text_editing(cr: &Context, shape: &Shape, caret: usize) {
+    let Shape::Text { pos, text, font, size, .. } = shape else { return; };
+
+    let (x, y, w, h) = text_rect(*pos, *size).as_f64();
+    cr.set_line_width(1.0);
+    cr.set_dash(&[4.0, 4.0], 0.0);
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.8);
+    cr.rectangle(x + 0.5, y + 0.5, w - 1.0, h - 1.0);
+    cr.stroke().expect("Cairo stroke failed");
+    cr.set_dash(&[], 0.0);
+
+    let layout = text_layout(&pangocairo::functions::create_context(cr), text, font);
+    let caret = layout.index_to_pos(caret.min(text.len()) as i32);
+    let caret_x = pos.0 as f64 + caret.x() as f64 / pango::SCALE as f64;
+    let caret_y = pos.1 as f64 + caret.y() as f64 / pango::SCALE as f64;
+
+    cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    cr.set_line_width(2.0);
+    cr.move_to(caret_x + 1.0, caret_y);
+    cr.line_to(caret_x + 1.0, caret_y + caret.height() as f64 / pango::SCALE as f64);
+    cr.stroke().expect("Cairo stroke failed");
+}
+
+/// Draws `source` enlarged into `target`, without smoothing so single pixels
+/// stay sharp, and outlines both. The connector is kept out of the source.
+pub fn draw_magnify(
+    surface: &TiledSurface,
+    cr: &Context,
+    source: &Rect,
+    target: &Rect,
+    color: (u8, u8, u8),
+    connector: bool,
+    style: &Style,
+) {
+    let Ok(pixels) = surface.region(source) else { return; };
+    let (sx, sy, sw, sh) = source.as_f64();
+    let (tx, ty, tw, th) = target.as_f64();
+
+    draw_styled(cr, style, color, cairo::Operator::Over, |cr, pass, color, width| {
+        set_color(cr, color, 1.0);
+        cr.set_line_width(width);
+
+        if connector {
+            let bounds = source.union(target).expand(style.margin());
+            let (bx, by, bw, bh) = bounds.as_f64();
+
+            cr.save().expect("Failed to save state");
+            cr.set_fill_rule(cairo::FillRule::EvenOdd);
+            cr.rectangle(bx, by, bw, bh);
+            cr.rectangle(sx, sy, sw, sh);
+            cr.clip();
+
+            cr.move_to(sx + sw / 2.0, sy + sh / 2.0);
+            cr.line_to(tx + tw / 2.0, ty + th / 2.0);
+            cr.stroke().expect("Cairo stroke failed");
+            cr.restore().expect("Failed to restore state");
+        }
+
+        match pass {
+            Pass::Main => {
+                cr.save().expect("Failed to save state");
+                cr.rectangle(tx, ty, tw, th);
+                cr.clip();
+                cr.translate(tx, ty);
+                cr.scale(tw / sw, th / sh);
+                cr.set_source_surface(&pixels, 0.0, 0.0).expect("Failed to set source");
+                cr.source().set_filter(cairo::Filter::Nearest);
+                cr.paint().expect("Failed to paint");
+                cr.restore().expect("Failed to restore state");
+            }
+            Pass::Shadow => {
+                cr.rectangle(tx, ty, tw, th);
+                cr.fill().expect("Cairo fill failed");
+            }
+            Pass::Outline => {}
+        }
+
+        let inset = width / 2.0;
+        cr.rectangle(sx - inset, sy - inset, sw + width, sh + width);
+        cr.rectangle(tx - inset, ty - inset, tw + width, th + width);
+        cr.stroke().expect("Cairo stroke failed");
+    });
+}
+
+/// Replaces every `block`×`block` square with its average colour. Unlike blur,
+/// nothing of the original detail is left to recover.
+pub fn draw_pixelate(surface: &TiledSurface, cr: &Context, rect: &Rect, block: i32) {
+    let Ok(source) = surface.region(rect) else { return; };
+    let (w, h, stride) = (rect.w as usize, rect.h as usize, source.stride() as usize);
+    let block = block.max(1) as usize;
+
+    let columns = w.div_ceil(block);
+    let mut sums = vec![[0u64; 5]; columns * h.div_ceil(block)];
+
+    let _ = source.with_data(|data| {
+        for y in 0..h {
+            for x in 0..w {
+                let offset = y * stride + x * 4;
+                let pixel = u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+                let sum = &mut sums[(y / block) * columns + x / block];
+
+                sum[0] += ((pixel >> 16) & 0xff) as u64;
+                sum[1] += ((pixel >> 8) & 0xff) as u64;
+                sum[2] += (pixel & 0xff) as u64;
+                sum[3] += (pixel >> 24) as u64;
+                sum[4] += 1;
+            }
+        }
+    });
+
+    cr.save().expect("Failed to save state");
+    cr.set_operator(cairo::Operator::Source);
+
+    for (index, [r, g, b, a, count]) in sums.into_iter().enumerate() {
+        if count == 0 || a == 0 {
+            continue;
+        }
+
+        // Channels are premultiplied, so dividing by alpha gives straight colour
+        let alpha = a as f64 / count as f64 / 255.0;
+        cr.set_source_rgba(r as f64 / a as f64, g as f64 / a as f64, b as f64 / a as f64, alpha);
+
+        let (bx, by) = ((index % columns * block) as f64, (index / columns * block) as f64);
+        cr.rectangle(
+            rect.x as f64 + bx,
+            rect.y as f64 + by,
+            (block as f64).min(rect.w as f64 - bx),
+            (block as f64).min(rect.h as f64 - by),
+        );
+        cr.fill().expect("Cairo fill failed");
+    }
+
+    cr.restore().expect("Failed to restore state");
+}
+
+/// Covers `rect` with opaque black.
+pub fn draw_redact(cr: &Context, rect: &Rect) {
+    let (x, y, w, h) = rect.as_f64();
+
+    cr.save().expect("Failed to save state");
+    cr.set_operator(cairo::Operator::Source);
+    cr.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+    cr.rectangle(x, y, w, h);
+    cr.fill().expect("Cairo fill failed");
+    cr.restore().expect("Failed to restore state");
+}
+
+/// Number of source pixels shown on each side of the loupe.
+const LOUPE_PIXELS: i32 = 17;
+const LOUPE_ZOOM: f64 = 8.0;
+
+/// Draws a zoomed view of the pixels around `pos` next to the cursor,
+/// with a pixel grid, crosshair and the coordinates and colour under it.
+pub fn draw_loupe(cr: &Context, surface: &TiledSurface, pos: (i32, i32), bounds: (f64, f64)) {
+    let half = LOUPE_PIXELS / 2;
+    let source_rect = Rect { x: pos.0 - half, y: pos.1 - half, w: LOUPE_PIXELS, h: LOUPE_PIXELS };
+
+    let Ok(source) = surface.region(&source_rect) else { return; };
+
+    let mut color = (0u8, 0u8, 0u8);
+    let _ = source.with_data(|data| {
+        let offset = (half * source.stride() + half * 4) as usize;
+        let pixel = u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        color = ((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8);
+    });
+
+    let size = LOUPE_PIXELS as f64 * LOUPE_ZOOM;
+    let label_height = 22.0;
+
+    // Keep the loupe on screen by flipping it to the other side of the cursor
+    let (mx, my) = (pos.0 as f64, pos.1 as f64);
+    let x = if mx + 24.0 + size > bounds.0 { mx - 24.0 - size } else { mx + 24.0 };
+    let y = if my + 24.0 + size + label_height > bounds.1 { my - 24.0 - size - label_height } else { my + 24.0 };
+
+    cr.save().expect("Failed to save state");
+
+    // Zoomed pixels
+    cr.rectangle(x, y, size, size);
+    cr.clip();
+    cr.translate(x, y);
+    cr.scale(LOUPE_ZOOM, LOUPE_ZOOM);
+    cr.set_source_surface(&source, 0.0, 0.0).expect("Failed to set source");
+    cr.source().set_filter(cairo::Filter::Nearest);
+    cr.paint().expect("Failed to paint");
+
+    cr.restore().expect("Failed to restore state");
+
+    // Pixel grid
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.12);
+    cr.set_line_width(1.0);
+    for i in 1..LOUPE_PIXELS {
+        let offset = i as f64 * LOUPE_ZOOM + 0.5;
+        cr.move_to(x + offset, y);
+        cr.line_to(x + offset, y + size);
+        cr.move_to(x, y + offset);
+        cr.line_to(x + size, y + offset);
+    }
+    cr.stroke().expect("Cairo stroke failed");
+
+    // Crosshair guides and the pixel under the cursor
+    let center = half as f64 * LOUPE_ZOOM;
+    cr.set_source_rgba(0.2, 0.6, 1.0, 0.5);
+    cr.rectangle(x, y + center, size, LOUPE_ZOOM);
+    cr.rectangle(x + center, y, LOUPE_ZOOM, size);
+    cr.fill().expect("Cairo fill failed");
+
+    cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    cr.rectangle(x + center + 0.5, y + center + 0.5, LOUPE_ZOOM - 1.0, LOUPE_ZOOM - 1.0);
+    cr.stroke().expect("Cairo stroke failed");
+
+    cr.rectangle(x + 0.5, y + 0.5, size - 1.0, size - 1.0);
+    cr.stroke().expect("Cairo stroke failed");
+
+    // Coordinates and colour readout
+    let label_y = y + size;
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.8);
+    cr.rectangle(x, label_y, size, label_height);
+    cr.fill().expect("Cairo fill failed");
+
+    set_color(cr, color, 1.0);
+    cr.rectangle(x + 5.0, label_y + 6.0, 10.0, 10.0);
+    cr.fill().expect("Cairo fill failed");
+
+    let text = format!("{},{}  #{:02X}{:02X}{:02X}", pos.0, pos.1, color.0, color.1, color.2);
+    cr.set_source_rgba(1.0, 1.0, 1.0, 1.0);
+    cr.select_font_face("monospace", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    cr.set_font_size(10.0);
+    cr.move_to(x + 20.0, label_y + 15.0);
+    cr.show_text(&text).expect("Cairo text failed");
+}
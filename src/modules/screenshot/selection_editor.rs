@@ -0,0 +1,211 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+use glib::clone;
+use gtk::{Box, Button, DrawingArea, DropDown, Entry, Grid, Label, Popover, SpinButton};
+use gtk::prelude::*;
+
+use crate::action::{AppAction, ScreenshotAction};
+use crate::common::hyprland::{self, Layout};
+use crate::modules::screenshot::{render, state::Rect};
+
+/// Largest coordinate accepted by the spin buttons, beyond any real layout.
+const MAX_COORD: f64 = 100_000.0;
+
+/// Popover for entering the exact selection geometry, either in slurp's
+/// `X,Y WxH` format or field by field relative to a monitor. Both show the
+/// same geometry and are applied the same way. One editor is shared by the
+/// selection label and the keyboard shortcut.
+#[derive(Debug)]
+pub struct SelectionEditor {
+    tx: Sender<AppAction>,
+    popover: Popover,
+    entry: Entry,
+    fields: [SpinButton; 4],
+    origin: DropDown,
+    /// Top-left corner of each origin choice in capture pixels, the whole screen first.
+    origins: Rc<Vec<(i32, i32)>>,
+    /// Origin the fields are currently shown relative to.
+    shown_origin: Rc<Cell<u32>>,
+}
+
+impl SelectionEditor {
+    pub fn new(tx: Sender<AppAction>, parent: &DrawingArea, capture_width: i32) -> Rc<Self> {
+        let entry = Entry::builder()
+            .width_chars(22)
+            .placeholder_text("X,Y WxH")
+            .build();
+
+        let fields = [
+            SpinButton::with_range(-MAX_COORD, MAX_COORD, 1.0),
+            SpinButton::with_range(-MAX_COORD, MAX_COORD, 1.0),
+            SpinButton::with_range(1.0, MAX_COORD, 1.0),
+            SpinButton::with_range(1.0, MAX_COORD, 1.0),
+        ];
+
+        let (names, origins) = monitor_origins(capture_width);
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let origin = DropDown::from_strings(&names);
+        let origins = Rc::new(origins);
+        let shown_origin = Rc::new(Cell::new(0));
+
+        let apply = Button::with_label("Apply");
+
+        let grid = Grid::builder()
+            .row_spacing(6)
+            .column_spacing(6)
+            .build();
+
+        grid.attach(&Label::new(Some("Origin")), 0, 0, 1, 1);
+        grid.attach(&origin, 1, 0, 3, 1);
+
+        for (index, (field, name)) in fields.iter().zip(["X", "Y", "W", "H"]).enumerate() {
+            let (column, row) = ((index % 2) as i32 * 2, 1 + (index / 2) as i32);
+            grid.attach(&Label::new(Some(name)), column, row, 1, 1);
+            grid.attach(field, column + 1, row, 1, 1);
+        }
+
+        grid.attach(&apply, 0, 3, 4, 1);
+
+        let content = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(8)
+            .build();
+        content.append(&entry);
+        content.append(&grid);
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .child(&content)
+            .build();
+        popover.set_parent(parent);
+
+        entry.connect_changed(|entry| entry.remove_css_class("error"));
+
+        let editor = Rc::new(Self { tx, popover, entry, fields, origin, origins, shown_origin });
+
+        editor.entry.connect_activate(clone!(
+            #[weak] editor,
+            move |entry| {
+                match parse_geometry(&entry.text()) {
+                    Some(rect) => editor.apply(rect),
+                    None => entry.add_css_class("error"),
+                }
+            }
+        ));
+
+        // Keep the absolute position when another origin is picked
+        editor.origin.connect_selected_notify(clone!(
+            #[weak] editor,
+            move |_| {
+                let rect = editor.fields_rect();
+                editor.shown_origin.set(editor.origin.selected());
+                editor.set_fields(&rect);
+            }
+        ));
+
+        apply.connect_clicked(clone!(
+            #[weak] editor,
+            move |_| editor.apply(editor.fields_rect())
+        ));
+
+        editor
+    }
+
+    /// Opens the editor on `selection`, pointing at its label, or at `pointer`
+    /// when nothing is selected yet.
+    pub fn open(&self, selection: &Rect, pointer: (i32, i32)) {
+        let anchor = if selection.is_empty() {
+            Rect { x: pointer.0, y: pointer.1, w: 1, h: 1 }
+        } else {
+            render::selection_label_rect(selection)
+        };
+
+        self.entry.set_text(&format!(
+            "{},{} {}x{}", selection.x, selection.y, selection.w, selection.h
+        ));
+        self.set_fields(selection);
+
+        self.popover.set_pointing_to(Some(&gdk::Rectangle::new(anchor.x, anchor.y, anchor.w, anchor.h)));
+        self.popover.popup();
+        self.fields[0].grab_focus();
+    }
+
+    fn apply(&self, rect: Rect) {
+        let _ = self.tx.send(AppAction::Screenshot(ScreenshotAction::SetSelection(rect)));
+        self.popover.popdown();
+    }
+
+    fn origin_offset(&self) -> (i32, i32) {
+        self.origins
+            .get(self.shown_origin.get() as usize)
+            .copied()
+            .unwrap_or((0, 0))
+    }
+
+    /// Absolute selection described by the fields.
+    fn fields_rect(&self) -> Rect {
+        let (ox, oy) = self.origin_offset();
+        let [x, y, w, h] = &self.fields;
+
+        // Commit text that was typed but not confirmed yet
+        for field in &self.fields {
+            field.update();
+        }
+
+        Rect {
+            x: x.value_as_int() + ox,
+            y: y.value_as_int() + oy,
+            w: w.value_as_int(),
+            h: h.value_as_int(),
+        }
+    }
+
+    fn set_fields(&self, rect: &Rect) {
+        let (ox, oy) = self.origin_offset();
+        let values = [rect.x - ox, rect.y - oy, rect.w.max(1), rect.h.max(1)];
+
+        for (field, value) in self.fields.iter().zip(values) {
+            field.set_value(value as f64);
+        }
+    }
+}
+
+/// Origin choices: the whole screen, then every monitor reported by Hyprland.
+fn monitor_origins(capture_width: i32) -> (Vec<String>, Vec<(i32, i32)>) {
+    let mut names = vec!["Screen".to_string()];
+    let mut origins = vec![(0, 0)];
+
+    let monitors = if hyprland::is_running() {
+        hyprland::monitors().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    if let Some(layout) = Layout::new(&monitors, capture_width) {
+        for monitor in &monitors {
+            let rect = layout.capture_rect(&monitor.logical_rect());
+            names.push(monitor.name.clone());
+            origins.push((rect.x, rect.y));
+        }
+    }
+
+    (names, origins)
+}
+
+/// Parses `X,Y WxH`, also accepting `×` as the size separator.
+fn parse_geometry(text: &str) -> Option<Rect> {
+    let (position, size) = text.trim().split_once(char::is_whitespace)?;
+    let (x, y) = position.split_once(',')?;
+    let (w, h) = size.trim().split_once(['x', '×'])?;
+
+    let rect = Rect {
+        x: x.trim().parse().ok()?,
+        y: y.trim().parse().ok()?,
+        w: w.trim().parse().ok()?,
+        h: h.trim().parse().ok()?,
+    };
+
+    (rect.w > 0 && rect.h > 0).then_some(rect)
+}
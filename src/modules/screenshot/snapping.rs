@@ -0,0 +1,229 @@
+use crate::common::tiled_surface::TiledSurface;
+use crate::modules::screenshot::state::{Rect, SelectionHitZone};
+
+/// How far, in pixels, an edge is pulled towards a detected boundary.
+const SNAP_DISTANCE: i32 = 8;
+/// Minimum luminance step between neighbouring pixels to count as an edge.
+const EDGE_CONTRAST: u8 = 24;
+/// Length of a straight edge needed to snap to it. Shorter spans need
+/// an edge along their whole length, which keeps text from attracting edges.
+const MIN_EDGE_RUN: usize = 32;
+
+/// One bit per pixel, stored row by row so a span of a row can be scanned directly.
+#[derive(Debug)]
+struct BitRows {
+    row_len: usize,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl BitRows {
+    fn new(rows: usize, row_len: usize) -> Self {
+        let words_per_row = row_len.div_ceil(64);
+        Self { row_len, words_per_row, words: vec![0; rows * words_per_row] }
+    }
+
+    fn set(&mut self, row: usize, i: usize) {
+        self.words[row * self.words_per_row + i / 64] |= 1 << (i % 64);
+    }
+
+    fn get(&self, row: usize, i: usize) -> bool {
+        self.words[row * self.words_per_row + i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Longest run of set bits in `row` between `start` and `end`.
+    fn longest_run(&self, row: usize, start: usize, end: usize) -> usize {
+        let mut longest = 0;
+        let mut current = 0;
+
+        for i in start..end.min(self.row_len) {
+            if self.get(row, i) {
+                current += 1;
+                longest = longest.max(current);
+            } else {
+                current = 0;
+            }
+        }
+
+        longest
+    }
+}
+
+/// Straight horizontal and vertical boundaries found in the capture,
+/// such as window borders, panels and buttons.
+#[derive(Debug)]
+pub struct EdgeMap {
+    width: i32,
+    height: i32,
+    /// Indexed by x; bit y is set when pixels (x - 1, y) and (x, y) differ.
+    vertical: BitRows,
+    /// Indexed by y; bit x is set when pixels (x, y - 1) and (x, y) differ.
+    horizontal: BitRows,
+}
+
+impl EdgeMap {
+    pub fn detect(image: &Luminance) -> Self {
+        let (width, height, luma) = (image.width, image.height, &image.pixels);
+
+        let mut vertical = BitRows::new(width, height);
+        let mut horizontal = BitRows::new(height, width);
+
+        for y in 0..height {
+            let row = &luma[y * width..(y + 1) * width];
+
+            for x in 1..width {
+                if row[x].abs_diff(row[x - 1]) >= EDGE_CONTRAST {
+                    vertical.set(x, y);
+                }
+            }
+
+            if y > 0 {
+                let above = &luma[(y - 1) * width..y * width];
+                for x in 0..width {
+                    if row[x].abs_diff(above[x]) >= EDGE_CONTRAST {
+                        horizontal.set(y, x);
+                    }
+                }
+            }
+        }
+
+        Self { width: width as i32, height: height as i32, vertical, horizontal }
+    }
+
+    /// Moves the edges of `rect` that `zone` drags onto nearby boundaries.
+    pub fn snap_rect(&self, rect: Rect, zone: &SelectionHitZone) -> Rect {
+        let (west, east, north, south) = zone.edges();
+        let (mut x1, mut y1) = (rect.x, rect.y);
+        let (mut x2, mut y2) = (rect.x + rect.w, rect.y + rect.h);
+
+        if west { x1 = self.snap_vertical(x1, y1, y2); }
+        if east { x2 = self.snap_vertical(x2, y1, y2); }
+        if north { y1 = self.snap_horizontal(y1, x1, x2); }
+        if south { y2 = self.snap_horizontal(y2, x1, x2); }
+
+        if x2 <= x1 || y2 <= y1 {
+            return rect;
+        }
+
+        Rect { x: x1, y: y1, w: x2 - x1, h: y2 - y1 }
+    }
+
+    fn snap_vertical(&self, x: i32, y1: i32, y2: i32) -> i32 {
+        Self::snap(&self.vertical, x, self.width, (y1, y2), self.height)
+    }
+
+    fn snap_horizontal(&self, y: i32, x1: i32, x2: i32) -> i32 {
+        Self::snap(&self.horizontal, y, self.height, (x1, x2), self.width)
+    }
+
+    /// Picks the line closest to `position` whose edge run along `span` is long enough.
+    fn snap(lines: &BitRows, position: i32, line_count: i32, span: (i32, i32), span_limit: i32) -> i32 {
+        let start = span.0.clamp(0, span_limit) as usize;
+        let end = span.1.clamp(0, span_limit) as usize;
+        let required = (end - start).min(MIN_EDGE_RUN);
+
+        if required == 0 {
+            return position;
+        }
+
+        (0..=SNAP_DISTANCE)
+            .flat_map(|d| [position - d, position + d])
+            .filter(|line| (1..line_count).contains(line))
+            .find(|line| lines.longest_run(*line as usize, start, end) >= required)
+            .unwrap_or(position)
+    }
+}
+
+/// Approximate luminance of every pixel of the capture, in row-major order.
+/// Unlike the capture itself it can be sent to another thread.
+#[derive(Debug)]
+pub struct Luminance {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Luminance {
+    pub fn from_surface(surface: &TiledSurface) -> anyhow::Result<Self> {
+        let (width, height) = (surface.width() as usize, surface.height() as usize);
+        let mut luma = vec![0u8; width * height];
+
+        for tile in surface.tiles() {
+            let (tw, stride) = (tile.surface.width() as usize, tile.surface.stride() as usize);
+
+            tile.surface.with_data(|data| {
+                for (row, src) in data.chunks(stride).enumerate().take(tile.surface.height() as usize) {
+                    let offset = (tile.y as usize + row) * width + tile.x as usize;
+                    let dst = &mut luma[offset..offset + tw];
+
+                    for (argb, out) in src[..tw * 4].chunks_exact(4).zip(dst.iter_mut()) {
+                        let pixel = u32::from_ne_bytes([argb[0], argb[1], argb[2], argb[3]]);
+                        let (r, g, b) = ((pixel >> 16) & 0xff, (pixel >> 8) & 0xff, pixel & 0xff);
+                        *out = ((r * 77 + g * 150 + b * 29) >> 8) as u8;
+                    }
+                }
+            })?;
+        }
+
+        Ok(Self { width, height, pixels: luma })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dark 200x200 image with bright blocks over `blocks`.
+    fn edges(blocks: &[Rect]) -> EdgeMap {
+        let (width, height) = (200, 200);
+        let mut pixels = vec![20u8; width * height];
+
+        for block in blocks {
+            for y in block.y..block.y + block.h {
+                for x in block.x..block.x + block.w {
+                    pixels[y as usize * width + x as usize] = 200;
+                }
+            }
+        }
+
+        EdgeMap::detect(&Luminance { width, height, pixels })
+    }
+
+    const WINDOW: Rect = Rect { x: 50, y: 40, w: 100, h: 120 };
+
+    #[test]
+    fn dragged_corner_snaps_to_nearby_window() {
+        let map = edges(&[WINDOW]);
+        let rect = Rect { x: 50, y: 40, w: 95, h: 115 };
+
+        assert_eq!(map.snap_rect(rect, &SelectionHitZone::SE), WINDOW);
+    }
+
+    #[test]
+    fn only_dragged_edges_snap() {
+        let map = edges(&[WINDOW]);
+        let rect = Rect { x: 53, y: 44, w: 90, h: 100 };
+
+        assert_eq!(map.snap_rect(rect, &SelectionHitZone::N), Rect { x: 53, y: 40, w: 90, h: 104 });
+        assert_eq!(map.snap_rect(rect, &SelectionHitZone::Inside), rect);
+    }
+
+    #[test]
+    fn distant_boundaries_are_ignored() {
+        let map = edges(&[WINDOW]);
+        let rect = Rect { x: 50, y: 40, w: 80, h: 120 };
+
+        assert_eq!(map.snap_rect(rect, &SelectionHitZone::E), rect);
+    }
+
+    #[test]
+    fn short_edges_only_attract_short_spans() {
+        let map = edges(&[Rect { x: 100, y: 100, w: 4, h: 4 }]);
+
+        let tall = Rect { x: 10, y: 60, w: 88, h: 80 };
+        assert_eq!(map.snap_rect(tall, &SelectionHitZone::E), tall);
+
+        let short = Rect { x: 10, y: 100, w: 88, h: 4 };
+        assert_eq!(map.snap_rect(short, &SelectionHitZone::E), Rect { x: 10, y: 100, w: 90, h: 4 });
+    }
+}
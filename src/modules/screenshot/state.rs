@@ -1,19 +1,61 @@
-use cairo::ImageSurface;
-
 use crate::capture::screenshot::export::export_selection;
 use crate::common::cursor;
+use crate::common::tiled_surface::TiledSurface;
+use crate::config::{Config, ExportConfig};
+use crate::modules::screenshot::history::{Edit, History};
+use crate::modules::screenshot::render;
+use crate::modules::screenshot::snapping::EdgeMap;
 
 #[derive(Debug, Clone)]
 pub struct ScreenshotState {
     selection: Selection,
-    paused: bool,
+    mode: OverlayMode,
     mouse_pos: (i32, i32),
     current_tool: Tool,
     current_color: (u8, u8, u8),
-    drag_start: Option<(i32, i32)>,
-    drag_origin: Option<Rect>,
-    drag_mode: Option<DragMode>,
+    /// Style given to new annotations.
+    style: Style,
+    drag: Option<Drag>,
     current_shape: Option<Shape>,
+    /// Placed annotations, bottom to top. They are drawn over the untouched capture.
+    shapes: Vec<Shape>,
+    /// Index in `shapes` of the annotation picked with the select tool.
+    selected_shape: Option<usize>,
+    /// Placed annotation being moved or resized, out of `shapes` until the drag ends.
+    moving: Option<MovingShape>,
+    /// Undo and redo steps for `shapes`.
+    history: History,
+    /// Bumped whenever `shapes` changes, so the canvas knows what to draw again.
+    shapes_revision: u64,
+    active_handle: SelectionHitZone,
+    aspect_ratio: Option<f64>,
+    extra_regions: Vec<Region>,
+    export: ExportConfig,
+    selection_mode: SelectionMode,
+    /// Freeform outline of the selection, relative to its rect. Empty for rectangles.
+    outline: Vec<(f64, f64)>,
+    lasso_points: Vec<(i32, i32)>,
+    polygon_points: Vec<(i32, i32)>,
+    /// Pango font description used for new text, e.g. `Sans 24`.
+    text_font: String,
+    text_background: bool,
+    fill: Fill,
+    /// Number shown on the next step marker.
+    next_marker: u32,
+    /// Edge length of the blocks drawn by the pixelate tool.
+    pixel_block: i32,
+    spotlight_ellipse: bool,
+    /// Darkness of the area outside the spotlights, in percent.
+    spotlight_opacity: u8,
+    /// Scale of the zoomed copy drawn by the magnify tool.
+    magnify_zoom: i32,
+    magnify_connector: bool,
+    /// Text box being typed into, baked once it is committed.
+    editing_text: Option<Shape>,
+    /// Placed text box reopened for editing, out of `shapes` until it is committed.
+    reopened_text: Option<MovingShape>,
+    /// Byte offset of the caret in `editing_text`, always on a char boundary.
+    text_caret: usize,
 }
 
 impl Default for ScreenshotState {
@@ -22,57 +64,598 @@ impl Default for ScreenshotState {
 
         Self {
             selection: Selection::idle(),
-            paused: false,
+            mode: OverlayMode::Selecting,
             mouse_pos: (0, 0),
             current_tool: Tool::None,
             current_color: (255, 0, 0),
-            drag_start: None,
-            drag_origin: None,
-            drag_mode: None,
-            current_shape: None
+            style: Style::default(),
+            drag: None,
+            current_shape: None,
+            shapes: Vec::new(),
+            selected_shape: None,
+            moving: None,
+            history: History::default(),
+            shapes_revision: 0,
+            active_handle: SelectionHitZone::Inside,
+            aspect_ratio: None,
+            extra_regions: Vec::new(),
+            export: ExportConfig::default(),
+            selection_mode: SelectionMode::Rectangle,
+            outline: Vec::new(),
+            lasso_points: Vec::new(),
+            polygon_points: Vec::new(),
+            text_font: "Sans 24".to_string(),
+            text_background: false,
+            fill: Fill { color: (255, 0, 0), opacity: 40 },
+            next_marker: 1,
+            pixel_block: 12,
+            spotlight_ellipse: false,
+            spotlight_opacity: 50,
+            magnify_zoom: 3,
+            magnify_connector: true,
+            editing_text: None,
+            reopened_text: None,
+            text_caret: 0,
         }
     }
 }
 
 impl ScreenshotState {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            export: config.export.clone(),
+            ..Self::default()
+        }
+    }
+
     // Immutable
     pub fn selection(&self) -> &Selection { &self.selection }
-    pub fn is_paused(&self) -> bool { self.paused }
+    pub fn mode(&self) -> OverlayMode { self.mode }
     pub fn mouse_pos(&self) -> (i32, i32) { self.mouse_pos }
     pub fn current_shape(&self) -> Option<&Shape> { self.current_shape.as_ref() }
     pub fn current_tool(&self) -> Tool { self.current_tool }
+    pub fn active_handle(&self) -> SelectionHitZone { self.active_handle }
+    pub fn export_config(&self) -> &ExportConfig { &self.export }
+
+    pub fn pending_polygon(&self) -> &[(i32, i32)] { &self.polygon_points }
+    pub fn editing_text(&self) -> Option<&Shape> { self.editing_text.as_ref() }
+    pub fn text_caret(&self) -> usize { self.text_caret }
+    pub fn spotlight_opacity(&self) -> u8 { self.spotlight_opacity }
+    pub fn shapes(&self) -> &[Shape] { &self.shapes }
+    pub fn shapes_revision(&self) -> u64 { self.shapes_revision }
+
+    /// Placed spotlights, plus the one being drawn or moved.
+    pub fn spotlights(&self) -> Vec<Spotlight> {
+        self.shapes
+            .iter()
+            .chain(self.current_shape.as_ref())
+            .filter_map(|shape| match shape {
+                Shape::Spotlight { rect, ellipse } => Some(Spotlight { rect: *rect, ellipse: *ellipse }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Frame of the annotation picked with the select tool, and whether it has resize handles.
+    pub fn selected_frame(&self) -> Option<(Rect, bool)> {
+        let shape = match &self.moving {
+            Some(_) => self.current_shape.as_ref(),
+            None => self.selected_shape.and_then(|index| self.shapes.get(index)),
+        }?;
+
+        let frame = shape.frame();
+        Some((frame, shape.is_resizable() && !frame.is_empty()))
+    }
+
+    /// The selection being edited, with its freeform outline if it has one.
+    pub fn primary_region(&self) -> Option<Region> {
+        if !self.selection.is_active() || self.selection.rect.is_empty() {
+            return None;
+        }
+
+        if self.outline.is_empty() {
+            return Some(Region::Rect(self.selection.rect));
+        }
+
+        let (x, y, w, h) = self.selection.rect.as_f64();
+        let points = self.outline.iter()
+            .map(|(nx, ny)| (x + nx * w, y + ny * h))
+            .collect();
+
+        Some(Region::Outline(points))
+    }
+
+    /// All selected regions, the selection being edited first.
+    pub fn regions(&self) -> Vec<Region> {
+        self.primary_region()
+            .into_iter()
+            .chain(self.extra_regions.iter().cloned())
+            .collect()
+    }
+
+    /// `true` in the editor, with or without an annotation tool.
+    pub fn is_editing(&self) -> bool {
+        matches!(self.mode, OverlayMode::Adjusting | OverlayMode::Annotating)
+    }
+
+    /// Quick mode exports as soon as a selection is finished.
+    pub fn is_quick_capture_done(&self) -> bool {
+        self.mode == OverlayMode::Selecting && self.selection.phase == SelectionPhase::Finalized
+    }
+
+    /// The magnifier follows the cursor while a selection is being made or resized.
+    pub fn shows_loupe(&self) -> bool {
+        match self.mode {
+            OverlayMode::Selecting => true,
+            OverlayMode::Adjusting => self.drag.is_some_and(|d| matches!(d.mode, DragMode::Create | DragMode::Resize(_))),
+            OverlayMode::Annotating | OverlayMode::Exporting => false,
+        }
+    }
     
+    /// Edges snap to boundaries in the capture while the selection is created or resized.
+    pub fn snaps_edges(&self) -> bool {
+        self.drag.is_some_and(|d| matches!(d.mode, DragMode::Create | DragMode::Resize(_)))
+    }
+
     // Mutable
-    pub fn toogle_pause(&mut self) {
-        if self.selection.is_active() && !self.paused {
-            self.paused = !self.paused;
+
+    /// Selecting → Adjusting, or straight to Annotating when a tool is already picked.
+    /// Returns `false` if there is nothing to edit yet.
+    pub fn enter_editor(&mut self) -> bool {
+        if self.mode != OverlayMode::Selecting || !self.selection.is_active() {
+            return false;
+        }
+
+        self.mode = Self::editor_mode(self.current_tool);
+        true
+    }
+
+    /// Adjusting or Annotating → Selecting, dropping anything half drawn.
+    /// An annotation being moved stays where it was dragged to.
+    /// The selection is kept, so the next drag exports it as in quick mode.
+    pub fn leave_editor(&mut self) -> bool {
+        if !self.is_editing() {
+            return false;
         }
+
+        self.drop_moving_shape();
+        self.mode = OverlayMode::Selecting;
+        self.drag = None;
+        self.current_shape = None;
+        true
+    }
+
+    /// Any mode → Exporting, placing whatever is still being drawn, moved or
+    /// typed so the export shows it. Returns `false` if already exporting or
+    /// nothing is selected.
+    pub fn begin_export(&mut self) -> bool {
+        if self.mode == OverlayMode::Exporting || self.regions().is_empty() {
+            return false;
+        }
+
+        self.drop_moving_shape();
+        if self.drag.take().is_some_and(|drag| drag.mode == DragMode::Draw) {
+            if let Some(shape) = self.current_shape.take() {
+                self.add_shape(shape);
+            }
+        }
+        self.current_shape = None;
+        self.finish_text();
+
+        self.mode = OverlayMode::Exporting;
+        true
+    }
+
+    /// Escape: abandons a pending polygon, then leaves the editor.
+    /// Returns `false` when there is nothing left to back out of.
+    pub fn cancel(&mut self) -> bool {
+        if !self.polygon_points.is_empty() {
+            self.polygon_points.clear();
+            self.selection = Selection::idle();
+            return true;
+        }
+
+        self.leave_editor()
     }
 
+    /// Picking a tool switches between Adjusting and Annotating.
     pub fn set_tool(&mut self, tool: Tool) {
+        self.drop_moving_shape();
         self.current_tool = tool;
+
+        if tool != Tool::Select {
+            self.selected_shape = None;
+        }
+
+        if self.is_editing() {
+            self.mode = Self::editor_mode(tool);
+            self.drag = None;
+            self.current_shape = None;
+        }
+    }
+
+    pub fn set_fill_color(&mut self, color: (u8, u8, u8)) {
+        self.fill.color = color;
+        self.edit_selected(|shape| shape.set_fill(|fill| fill.color = color));
+    }
+
+    pub fn set_fill_opacity(&mut self, opacity: u8) {
+        self.fill.opacity = opacity.min(100);
+        self.edit_selected(|shape| shape.set_fill(|fill| fill.opacity = opacity.min(100)));
+    }
+
+    pub fn set_pixel_block(&mut self, block: i32) {
+        self.pixel_block = block.max(2);
+    }
+
+    pub fn set_spotlight_ellipse(&mut self, ellipse: bool) {
+        self.spotlight_ellipse = ellipse;
+    }
+
+    pub fn set_spotlight_opacity(&mut self, opacity: u8) {
+        self.spotlight_opacity = opacity.min(100);
+    }
+
+    pub fn set_magnify_zoom(&mut self, zoom: i32) {
+        self.magnify_zoom = zoom.clamp(2, 8);
+    }
+
+    pub fn set_magnify_connector(&mut self, connector: bool) {
+        self.magnify_connector = connector;
+    }
+
+    /// Starts numbering step markers from 1 again.
+    pub fn reset_markers(&mut self) {
+        self.next_marker = 1;
+    }
+
+    /// Adds an annotation on top of the others, unless it is too small to see.
+    pub fn add_shape(&mut self, shape: Shape) {
+        if !shape.is_valid() {
+            return;
+        }
+
+        let before = self.next_marker;
+        if let Shape::Marker { number, .. } = shape {
+            self.next_marker = number + 1;
+        }
+
+        self.history.apply(&mut self.shapes, Edit::Add(shape), (before, self.next_marker));
+        self.shapes_revision += 1;
+    }
+
+    pub fn delete_selected_shape(&mut self) {
+        self.drop_moving_shape();
+
+        if let Some(index) = self.selected_shape.take().filter(|index| *index < self.shapes.len()) {
+            let shape = self.shapes[index].clone();
+            self.history.apply(&mut self.shapes, Edit::Remove(index, shape), (self.next_marker, self.next_marker));
+            self.shapes_revision += 1;
+        }
+    }
+
+    /// Reverts the last change to the annotations, after committing the
+    /// text being typed.
+    pub fn undo(&mut self) {
+        self.finish_text();
+        self.drop_moving_shape();
+
+        if self.history.undo(&mut self.shapes, &mut self.next_marker) {
+            self.history_moved();
+        }
+    }
+
+    /// Makes the last undone change again.
+    pub fn redo(&mut self) {
+        self.finish_text();
+        self.drop_moving_shape();
+
+        if self.history.redo(&mut self.shapes, &mut self.next_marker) {
+            self.history_moved();
+        }
+    }
+
+    /// Drops the selection, whose index may be stale.
+    fn history_moved(&mut self) {
+        self.selected_shape = None;
+        self.shapes_revision += 1;
+    }
+
+    /// Changes the selected annotation, keeping the previous version for undo.
+    fn edit_selected(&mut self, edit: impl FnOnce(&mut Shape)) {
+        self.drop_moving_shape();
+        let Some(index) = self.selected_shape.filter(|index| *index < self.shapes.len()) else { return; };
+
+        let mut edited = self.shapes[index].clone();
+        edit(&mut edited);
+
+        if edited != self.shapes[index] {
+            let before = self.shapes[index].clone();
+            let markers = (self.next_marker, self.next_marker);
+            self.history.apply(&mut self.shapes, Edit::Replace(index, before, edited), markers);
+            self.shapes_revision += 1;
+        }
+    }
+
+    /// Puts the annotation being moved back in its place, recording the move for undo.
+    fn drop_moving_shape(&mut self) {
+        let Some(moving) = self.moving.take() else { return; };
+        let shape = self.current_shape.take().unwrap_or_else(|| moving.original.clone());
+        let index = moving.index.min(self.shapes.len());
+
+        self.shapes.insert(index, moving.original.clone());
+        self.shapes_revision += 1;
+        if shape != moving.original {
+            let markers = (self.next_marker, self.next_marker);
+            self.history.apply(&mut self.shapes, Edit::Replace(index, moving.original, shape), markers);
+        }
+        self.selected_shape = Some(index);
+    }
+
+    /// Starts moving the selected annotation, or resizing it from one of its
+    /// handles. Otherwise picks the topmost annotation under the pointer.
+    fn begin_shape_drag(&mut self, x: i32, y: i32) {
+        let handle = self.selected_frame()
+            .filter(|(_, resizable)| *resizable)
+            .map(|(frame, _)| cursor::get_cursor_zone(&frame, (x, y), Some(10)))
+            .filter(|zone| !matches!(zone, SelectionHitZone::Inside | SelectionHitZone::Outside));
+
+        let (index, mode) = match (handle, self.selected_shape) {
+            (Some(zone), Some(index)) => (index, DragMode::ResizeShape(zone)),
+            _ => {
+                self.selected_shape = self.shapes.iter().rposition(|shape| shape.hit((x, y)));
+                let Some(index) = self.selected_shape else { return; };
+                (index, DragMode::MoveShape)
+            }
+        };
+
+        let shape = self.shapes.remove(index);
+        self.shapes_revision += 1;
+        self.drag = Some(Drag { start: (x, y), origin: shape.frame(), mode });
+        self.moving = Some(MovingShape { index, original: shape.clone() });
+        self.current_shape = Some(shape);
+    }
+
+    pub fn set_text_font(&mut self, font: String) {
+        if let Some(Shape::Text { font: editing, .. }) = &mut self.editing_text {
+            editing.clone_from(&font);
+        }
+        self.text_font = font;
+    }
+
+    pub fn set_text_background(&mut self, background: bool) {
+        if let Some(Shape::Text { background: editing, .. }) = &mut self.editing_text {
+            *editing = background;
+        }
+        self.text_background = background;
+    }
+
+    /// Inserts `input` at the caret and moves the caret past it.
+    pub fn insert_text(&mut self, input: &str) {
+        if let Some(Shape::Text { text, .. }) = &mut self.editing_text {
+            text.insert_str(self.text_caret, input);
+            self.text_caret += input.len();
+        }
+    }
+
+    /// Backspace: removes the character before the caret.
+    pub fn delete_text_backward(&mut self) {
+        if let Some(Shape::Text { text, .. }) = &mut self.editing_text {
+            if let Some(c) = text[..self.text_caret].chars().next_back() {
+                self.text_caret -= c.len_utf8();
+                text.remove(self.text_caret);
+            }
+        }
+    }
+
+    /// Delete: removes the character after the caret.
+    pub fn delete_text_forward(&mut self) {
+        if let Some(Shape::Text { text, .. }) = &mut self.editing_text {
+            if self.text_caret < text.len() {
+                text.remove(self.text_caret);
+            }
+        }
+    }
+
+    /// Moves the caret one character to the right, or to the left if not `forward`.
+    pub fn move_text_caret(&mut self, forward: bool) {
+        let Some(Shape::Text { text, .. }) = &self.editing_text else { return; };
+
+        let step = match forward {
+            true => text[self.text_caret..].chars().next().map(|c| c.len_utf8() as isize),
+            false => text[..self.text_caret].chars().next_back().map(|c| -(c.len_utf8() as isize)),
+        };
+
+        if let Some(step) = step {
+            self.text_caret = self.text_caret.saturating_add_signed(step);
+        }
+    }
+
+    /// Stores the measured size of the text being edited, see [`render::measure_text`].
+    pub fn set_text_size(&mut self, measured: (i32, i32)) {
+        if let Some(Shape::Text { size, .. }) = &mut self.editing_text {
+            *size = measured;
+        }
+    }
+
+    /// Closes the text box being edited and adds it to the annotations. A
+    /// reopened one goes back in its place, recording the change for undo,
+    /// or is removed if it was emptied. Returns `true` if there was one.
+    pub fn finish_text(&mut self) -> bool {
+        let Some(text) = self.editing_text.take() else { return false; };
+
+        let Some(reopened) = self.reopened_text.take() else {
+            self.add_shape(text);
+            return true;
+        };
+
+        let index = reopened.index.min(self.shapes.len());
+        self.shapes.insert(index, reopened.original.clone());
+        self.shapes_revision += 1;
+
+        let markers = (self.next_marker, self.next_marker);
+        if !text.is_valid() {
+            self.history.apply(&mut self.shapes, Edit::Remove(index, reopened.original), markers);
+        } else if text != reopened.original {
+            self.history.apply(&mut self.shapes, Edit::Replace(index, reopened.original, text), markers);
+        }
+        true
     }
 
+    /// Reopens the topmost placed text box under the pointer, with the caret
+    /// at its end, or starts a new one there.
+    fn begin_text(&mut self, x: i32, y: i32) {
+        let hit = self.shapes.iter().rposition(|shape| matches!(shape, Shape::Text { .. }) && shape.hit((x, y)));
+
+        if let Some(index) = hit {
+            let shape = self.shapes.remove(index);
+            self.shapes_revision += 1;
+            self.text_caret = match &shape {
+                Shape::Text { text, .. } => text.len(),
+                _ => 0,
+            };
+            self.reopened_text = Some(MovingShape { index, original: shape.clone() });
+            self.editing_text = Some(shape);
+            return;
+        }
+
+        self.editing_text = Some(Shape::Text {
+            pos: (x, y),
+            text: String::new(),
+            font: self.text_font.clone(),
+            color: self.current_color,
+            background: self.text_background,
+            style: self.style,
+            size: (0, 0),
+        });
+        self.text_caret = 0;
+    }
+
+    fn editor_mode(tool: Tool) -> OverlayMode {
+        if tool == Tool::None { OverlayMode::Adjusting } else { OverlayMode::Annotating }
+    }
+
+    /// Colour of new annotations and of the selected one.
     pub fn set_color(&mut self, color: (u8, u8, u8)) {
         self.current_color = color;
+        self.edit_selected(|shape| shape.set_color(color));
     }
 
-    pub fn begin_drag(&mut self, x: i32, y: i32) {
-        self.mouse_pos = (x, y);
+    pub fn set_stroke_width(&mut self, width: u8) {
+        self.restyle(|style| style.width = width.max(1));
+    }
+
+    pub fn set_dash(&mut self, dash: Dash) {
+        self.restyle(|style| style.dash = dash);
+    }
+
+    pub fn set_opacity(&mut self, opacity: u8) {
+        self.restyle(|style| style.opacity = opacity.min(100));
+    }
+
+    pub fn set_contrast(&mut self, contrast: Contrast) {
+        self.restyle(|style| style.contrast = contrast);
+    }
 
-        if self.current_tool != Tool::None {
-            if self.paused
-                && self.selection.is_active()
-                && self.selection.rect.contains((x, y))
-            {
-                self.current_shape = None;
-                self.drag_start = Some((x, y));
+    pub fn set_arrowhead(&mut self, arrowhead: Arrowhead) {
+        self.restyle(|style| style.arrowhead = arrowhead);
+    }
+
+    /// Changes the style of new annotations and of the selected one.
+    fn restyle(&mut self, change: impl Fn(&mut Style)) {
+        change(&mut self.style);
+        self.edit_selected(|shape| {
+            if let Some(style) = shape.style_mut() {
+                change(style);
+            }
+        });
+    }
+
+    pub fn set_selection_mode(&mut self, mode: SelectionMode) {
+        self.selection_mode = mode;
+        self.polygon_points.clear();
+    }
+
+    /// Finishes the polygon being drawn. Returns `false` if it has too few points.
+    pub fn close_polygon(&mut self) -> bool {
+        let points = std::mem::take(&mut self.polygon_points);
+
+        if points.len() < 3 {
+            return false;
+        }
+
+        let (rect, outline) = Self::normalize_outline(&points);
+        self.selection = Selection::finalized(rect);
+        self.outline = outline;
+        true
+    }
+
+    /// Replaces the whole selection with `rect`, dropping any freeform outline
+    /// and extra regions, which were drawn for the previous selection.
+    pub fn set_selection_rect(&mut self, rect: Rect) {
+        if !rect.is_empty() {
+            self.selection = Selection::finalized(rect);
+            self.outline.clear();
+            self.extra_regions.clear();
+            self.polygon_points.clear();
+        }
+    }
+
+    /// A fixed size is moved, and shrunk if need be, to stay within `bounds`.
+    pub fn apply_preset(&mut self, preset: SelectionPreset, bounds: &Rect) {
+        match preset {
+            SelectionPreset::Free => self.aspect_ratio = None,
+            SelectionPreset::Ratio(w, h) => {
+                let ratio = w as f64 / h as f64;
+                self.aspect_ratio = Some(ratio);
+
+                if self.selection.phase == SelectionPhase::Finalized {
+                    let rect = self.selection.rect;
+                    self.selection.rect = Self::constrain_ratio(rect, &SelectionHitZone::SE, ratio);
+                }
+            }
+            SelectionPreset::Size(w, h) => {
+                self.aspect_ratio = None;
+
+                if self.selection.phase == SelectionPhase::Finalized {
+                    let rect = &mut self.selection.rect;
+                    rect.w = w.min(bounds.w);
+                    rect.h = h.min(bounds.h);
+                    rect.x = rect.x.min(bounds.x + bounds.w - rect.w).max(bounds.x);
+                    rect.y = rect.y.min(bounds.y + bounds.h - rect.h).max(bounds.y);
+                }
             }
-            return;
         }
+    }
+
+    /// Holding Ctrl while starting a new selection keeps the current one as an
+    /// extra region instead of replacing it.
+    pub fn begin_drag(&mut self, x: i32, y: i32, modifiers: Modifiers) {
+        self.mouse_pos = (x, y);
+
+        match self.mode {
+            OverlayMode::Exporting => return,
+            OverlayMode::Annotating => {
+                if !self.regions().iter().any(|r| r.bounds().contains((x, y))) {
+                    return;
+                }
 
-        self.drag_start = Some((x, y));
-        self.drag_origin = Some(self.selection.rect);
+                if self.current_tool == Tool::Select {
+                    self.begin_shape_drag(x, y);
+                } else if self.current_tool == Tool::Text {
+                    self.begin_text(x, y);
+                } else {
+                    self.drag = Some(Drag { start: (x, y), origin: self.selection.rect, mode: DragMode::Draw });
+
+                    // A marker is placed on click and can be dragged into position
+                    self.current_shape = (self.current_tool == Tool::Marker)
+                        .then(|| self.get_current_shape(false))
+                        .flatten();
+                }
+                return;
+            }
+            OverlayMode::Selecting | OverlayMode::Adjusting => {}
+        }
 
         let zone = cursor::get_cursor_zone(
             &self.selection.rect,
@@ -80,47 +663,107 @@ impl ScreenshotState {
             Some(10)
         );
 
-        self.drag_mode = Some(match zone {
+        if self.selection_mode == SelectionMode::Polygon
+            && (zone == SelectionHitZone::Outside || !self.polygon_points.is_empty())
+        {
+            self.add_polygon_point((x, y), modifiers);
+            return;
+        }
+
+        let mode = match zone {
             SelectionHitZone::Outside => DragMode::Create,
             SelectionHitZone::Inside => DragMode::Move,
             z => DragMode::Resize(z),
-        });
+        };
 
-        self.selection = Selection::dragging(self.selection.rect);
+        self.drag = Some(Drag { start: (x, y), origin: self.selection.rect, mode });
 
-    }
+        if mode == DragMode::Create {
+            self.start_new_region(modifiers);
+            self.lasso_points = vec![(x, y)];
+        }
 
-    pub fn update_drag(&mut self, dx: i32, dy: i32) {
-        let Some((start_x, start_y)) = self.drag_start else { return; };
+        self.selection = Selection::dragging(self.selection.rect);
 
-        if self.current_tool != Tool::None {
-            self.current_shape = self.get_current_shape();
-            return;
-        }
+    }
 
-        let Some(origin) = self.drag_origin else { return; };
-        let Some(mode) = self.drag_mode else { return; };
+    /// Shift constrains the selection to the preset ratio, a square when creating,
+    /// or the ratio the selection had before resizing. Otherwise the dragged
+    /// edges snap to `edges` when given.
+    pub fn update_drag(&mut self, dx: i32, dy: i32, modifiers: Modifiers, edges: Option<&EdgeMap>) {
+        let Some(Drag { start: (start_x, start_y), origin, mode }) = self.drag else { return; };
 
         let cx = dx + start_x;
         let cy = dy + start_y;
 
         match mode {
+            DragMode::Draw => {
+                self.mouse_pos = (cx, cy);
+
+                // Freehand strokes grow point by point instead of being rebuilt
+                match &mut self.current_shape {
+                    Some(Shape::Stroke { points, .. }) => {
+                        if points.last() != Some(&(cx, cy)) {
+                            points.push((cx, cy));
+                        }
+                    }
+                    _ => self.current_shape = self.get_current_shape(modifiers.shift),
+                }
+                return;
+            }
+
+            DragMode::MoveShape | DragMode::ResizeShape(_) => {
+                let Some(moving) = &self.moving else { return; };
+                let mut shape = moving.original.clone();
+
+                match mode {
+                    DragMode::ResizeShape(zone) => shape.fit(&origin, &Self::resize_rect(&origin, &zone, dx, dy).0),
+                    _ => shape.translate(dx, dy),
+                }
+                self.current_shape = Some(shape);
+                return;
+            }
+
+            DragMode::Create if self.selection_mode == SelectionMode::Lasso => {
+                let last = self.lasso_points.last().copied().unwrap_or((start_x, start_y));
+
+                if (cx - last.0).abs() + (cy - last.1).abs() >= 2 {
+                    self.lasso_points.push((cx, cy));
+                    let (rect, outline) = Self::normalize_outline(&self.lasso_points);
+                    self.selection.rect = rect;
+                    self.outline = outline;
+                }
+            }
+
             DragMode::Create => {
-                self.selection.rect.x = start_x.min(cx);
-                self.selection.rect.y = start_y.min(cy);
-                self.selection.rect.w = (cx - start_x).abs();
-                self.selection.rect.h = (cy - start_y).abs();
+                let rect = Self::rect_from_points((start_x, start_y), (cx, cy));
+                let ratio = self.aspect_ratio.or(modifiers.shift.then_some(1.0));
+                let corner = Self::corner_towards((start_x, start_y), (cx, cy));
+
+                self.selection.rect = match (ratio, edges) {
+                    (Some(ratio), _) => Self::constrain_ratio(rect, &corner, ratio),
+                    (None, Some(edges)) => edges.snap_rect(rect, &corner),
+                    (None, None) => rect,
+                };
             }
 
             DragMode::Move => {
-                if self.current_tool == Tool::None {
-                    self.selection.rect.x = origin.x + dx;
-                    self.selection.rect.y = origin.y + dy;
-                }
+                self.selection.rect.x = origin.x + dx;
+                self.selection.rect.y = origin.y + dy;
             }
 
             DragMode::Resize(zone) => {
-                self.selection.rect = self.resize_rect(&zone, dx, dy);
+                let (rect, zone) = Self::resize_rect(&origin, &zone, dx, dy);
+                let ratio = self.aspect_ratio.or_else(|| {
+                    (modifiers.shift && !origin.is_empty())
+                        .then(|| origin.w as f64 / origin.h as f64)
+                });
+
+                self.selection.rect = match (ratio, edges) {
+                    (Some(ratio), _) => Self::constrain_ratio(rect, &zone, ratio),
+                    (None, Some(edges)) => edges.snap_rect(rect, &zone),
+                    (None, None) => rect,
+                };
             }
         }
 
@@ -128,126 +771,350 @@ impl ScreenshotState {
         
     }
 
-    pub fn end_drag(&mut self) {
-        self.drag_start = None;
-        self.drag_origin = None;
-        self.drag_mode = None;
-        self.current_shape = None;
+    /// Returns `true` if the drag created, moved or resized the selection,
+    /// which is what finishes a quick capture. A plain click doesn't.
+    pub fn end_drag(&mut self) -> bool {
+        let Some(drag) = self.drag.take() else { return false; };
+        let created_lasso = drag.mode == DragMode::Create
+            && self.selection_mode == SelectionMode::Lasso;
+
+        match drag.mode {
+            DragMode::Draw => {
+                if let Some(shape) = self.current_shape.take() {
+                    self.add_shape(shape);
+                }
+                return false;
+            }
+            DragMode::MoveShape | DragMode::ResizeShape(_) => {
+                self.drop_moving_shape();
+                return false;
+            }
+            DragMode::Create | DragMode::Move | DragMode::Resize(_) => {}
+        }
+
+        // A polygon keeps collecting points until it is closed
+        if !self.polygon_points.is_empty() {
+            return false;
+        }
 
-        if self.selection.is_active() {
+        if created_lasso && std::mem::take(&mut self.lasso_points).len() < 3 {
+            self.outline.clear();
+            self.selection = Selection::idle();
+            return false;
+        }
+
+        if self.selection.is_active() && !self.selection.rect.is_empty() {
             self.selection = Selection::finalized(self.selection.rect);
+            drag.mode == DragMode::Create || self.selection.rect != drag.origin
         } else {
             self.selection = Selection::idle();
+            false
+        }
+    }
+
+    /// Moves the active handle of a finalized selection, or the whole selection
+    /// when no edge is active, keeping it within `bounds`.
+    pub fn nudge(&mut self, dx: i32, dy: i32, bounds: &Rect) {
+        self.nudge_handle(self.active_handle, dx, dy, bounds);
+    }
+
+    /// Ignored while annotating, where the arrow keys don't belong to the selection.
+    pub fn nudge_handle(&mut self, zone: SelectionHitZone, dx: i32, dy: i32, bounds: &Rect) {
+        if self.selection.phase != SelectionPhase::Finalized
+            || !matches!(self.mode, OverlayMode::Selecting | OverlayMode::Adjusting)
+        {
+            return;
+        }
+
+        let rect = &mut self.selection.rect;
+        match zone {
+            SelectionHitZone::Inside => {
+                rect.x = (rect.x + dx).min(bounds.x + bounds.w - rect.w).max(bounds.x);
+                rect.y = (rect.y + dy).min(bounds.y + bounds.h - rect.h).max(bounds.y);
+            }
+            SelectionHitZone::Outside => {}
+            zone => {
+                let (resized, flipped) = Self::resize_rect(rect, &zone, dx, dy);
+                let Some(resized) = resized.intersection(bounds) else { return; };
+                *rect = resized;
+
+                // Keep following the same edge after it crossed the opposite one
+                if self.active_handle == zone {
+                    self.active_handle = flipped;
+                }
+            }
         }
     }
 
+    /// Cycles the handle moved by [`Self::nudge`], clockwise starting from the top edge.
+    pub fn cycle_handle(&mut self, forward: bool) {
+        const ORDER: [SelectionHitZone; 9] = [
+            SelectionHitZone::Inside,
+            SelectionHitZone::N, SelectionHitZone::NE,
+            SelectionHitZone::E, SelectionHitZone::SE,
+            SelectionHitZone::S, SelectionHitZone::SW,
+            SelectionHitZone::W, SelectionHitZone::NW,
+        ];
+
+        let index = ORDER.iter().position(|z| *z == self.active_handle).unwrap_or(0);
+        let next = if forward { index + 1 } else { index + ORDER.len() - 1 };
+
+        self.active_handle = ORDER[next % ORDER.len()];
+    }
+
     pub fn set_mouse_pos(&mut self, pos: (i32, i32)) {
         self.mouse_pos = pos;
     }
 
-    pub fn export_selection(&self, original_surface: &ImageSurface) -> anyhow::Result<Vec<u8>> {
+    pub fn export_selection(&self, original_surface: &TiledSurface) -> anyhow::Result<Vec<u8>> {
         export_selection(original_surface, self)
     }
 
-    fn get_current_shape(&self) -> Option<Shape> {
+    /// `square` keeps rectangles and ellipses square while Shift is held.
+    fn get_current_shape(&self, square: bool) -> Option<Shape> {
 
-        let from = self.drag_start.unwrap();
+        let from = self.drag?.start;
         let to = self.mouse_pos;
+        let rect = if square {
+            Self::constrain_ratio(Self::rect_from_points(from, to), &Self::corner_towards(from, to), 1.0)
+        } else {
+            Self::rect_from_points(from, to)
+        };
+        let fill = matches!(self.current_tool, Tool::FilledRectangle | Tool::FilledEllipse)
+            .then_some(self.fill);
 
         match self.current_tool {
             Tool::Arrow => {
                 return Some(Shape::Arrow { 
                     from: from,
                     to: to,
-                    color: self.current_color
+                    color: self.current_color,
+                    style: self.style,
                 });
             }
 
-            Tool::Rectangle => {
+            Tool::Rectangle | Tool::FilledRectangle => {
                 return Some(Shape::Rectangle {
-                    rect: Self::rect_from_points(from, to),
-                    color: self.current_color 
+                    rect,
+                    color: self.current_color,
+                    fill,
+                    style: self.style,
                 });
             }
 
             Tool::Blur => {
                 return Some(Shape::Blur { rect: Self::rect_from_points(from, to) });
             }
+
+            Tool::Pen | Tool::Highlighter => Some(Shape::Stroke {
+                points: vec![from, to],
+                color: self.current_color,
+                highlighter: self.current_tool == Tool::Highlighter,
+                style: self.style,
+            }),
+
+            Tool::Line => Some(Shape::Line { from, to, color: self.current_color, style: self.style }),
+
+            Tool::Pixelate => Some(Shape::Pixelate {
+                rect: Self::rect_from_points(from, to),
+                block: self.pixel_block,
+            }),
+
+            Tool::Redact => Some(Shape::Redact { rect: Self::rect_from_points(from, to) }),
+
+            Tool::Spotlight => Some(Shape::Spotlight { rect, ellipse: self.spotlight_ellipse }),
+
+            Tool::Magnify => {
+                let source = Self::rect_from_points(from, to);
+                Some(Shape::Magnify {
+                    source,
+                    target: Self::place_magnified(&source, self.magnify_zoom, &self.selection.rect),
+                    color: self.current_color,
+                    connector: self.magnify_connector,
+                    style: self.style,
+                })
+            }
+
+            Tool::Marker => Some(Shape::Marker {
+                center: to,
+                number: self.next_marker,
+                color: self.current_color,
+                style: self.style,
+            }),
+
+            Tool::Ellipse | Tool::FilledEllipse => Some(Shape::Ellipse {
+                rect,
+                color: self.current_color,
+                fill,
+                style: self.style,
+            }),
             _ => {None}
         }
 
     }
 
-    fn resize_rect(&self, zone: &SelectionHitZone, x: i32, y: i32) -> Rect {
+    /// Keeps the current selection as an extra region when Ctrl is held,
+    /// otherwise starts over with a single region.
+    fn start_new_region(&mut self, modifiers: Modifiers) {
+        if modifiers.ctrl && self.is_editing() && self.selection.phase == SelectionPhase::Finalized {
+            self.extra_regions.extend(self.primary_region());
+        } else {
+            self.extra_regions.clear();
+        }
 
-        let origin = self.drag_origin.unwrap();
-        let mut rect = origin;
+        self.outline.clear();
+    }
 
-        match zone {
-            // Corners
-            SelectionHitZone::NW => {
-                rect.x = origin.x + x;
-                rect.y = origin.y + y;
-                rect.w = (origin.w - x).max(1);
-                rect.h = (origin.h - y).max(1);
-            }
+    fn add_polygon_point(&mut self, point: (i32, i32), modifiers: Modifiers) {
+        if self.polygon_points.is_empty() {
+            self.start_new_region(modifiers);
+            self.selection = Selection::dragging(Rect::zero());
+        }
 
-            SelectionHitZone::NE => {
-                rect.y = origin.y + y;
-                rect.w = (origin.w + x).max(1);
-                rect.h = (origin.h - y).max(1);
+        // Clicking the first point again closes the polygon
+        if let Some(first) = self.polygon_points.first() {
+            let near_first = (point.0 - first.0).abs() <= 8 && (point.1 - first.1).abs() <= 8;
+            if near_first && self.close_polygon() {
+                return;
             }
+        }
 
-            SelectionHitZone::SE => {
-                rect.w = (origin.w + x).max(1);
-                rect.h = (origin.h + y).max(1);
-            }
+        self.polygon_points.push(point);
+    }
 
-            SelectionHitZone::SW => {
-                rect.x = origin.x + x;
-                rect.w = (origin.w - x).max(1);
-                rect.h = (origin.h + y).max(1);
-            }
-            
-            // Sides
-            SelectionHitZone::N => {
-                rect.y = origin.y + y;
-                rect.h = (origin.h - y).max(1);
-            }
+    /// Returns the bounding box of `points` and the points relative to it.
+    fn normalize_outline(points: &[(i32, i32)]) -> (Rect, Vec<(f64, f64)>) {
+        let x1 = points.iter().map(|p| p.0).min().unwrap_or(0);
+        let y1 = points.iter().map(|p| p.1).min().unwrap_or(0);
+        let x2 = points.iter().map(|p| p.0).max().unwrap_or(0);
+        let y2 = points.iter().map(|p| p.1).max().unwrap_or(0);
+
+        let rect = Rect { x: x1, y: y1, w: (x2 - x1).max(1), h: (y2 - y1).max(1) };
+        let outline = points.iter()
+            .map(|p| (
+                (p.0 - rect.x) as f64 / rect.w as f64,
+                (p.1 - rect.y) as f64 / rect.h as f64,
+            ))
+            .collect();
+
+        (rect, outline)
+    }
 
-            SelectionHitZone::S => {
-                rect.h = (origin.h + y).max(1);
-            }
+    /// Moves the edges of `zone` by (`x`, `y`). Dragging an edge past the opposite
+    /// one flips the selection; the returned zone is the dragged handle after flipping.
+    fn resize_rect(origin: &Rect, zone: &SelectionHitZone, x: i32, y: i32) -> (Rect, SelectionHitZone) {
 
-            SelectionHitZone::W => {
-                rect.x = origin.x + x;
-                rect.w = (origin.w - x).max(1);
-            }
+        let (mut left, mut right) = (origin.x, origin.x + origin.w);
+        let (mut top, mut bottom) = (origin.y, origin.y + origin.h);
+        let (west, east, north, south) = zone.edges();
 
-            SelectionHitZone::E => {
-                rect.w = (origin.w + x).max(1);
-            }
+        if west { left += x; }
+        if east { right += x; }
+        if north { top += y; }
+        if south { bottom += y; }
 
-            _ => {}
-        }
+        let rect = Rect {
+            x: left.min(right),
+            y: top.min(bottom),
+            w: (right - left).abs().max(1),
+            h: (bottom - top).abs().max(1),
+        };
 
-        rect
+        (rect, zone.flipped(left > right, top > bottom))
 
     }
 
-    fn rect_from_points(from: (i32, i32), to: (i32, i32)) -> Rect {
-        let x1 = from.0.min(to.0);
-        let y1 = from.1.min(to.1);
-        let x2 = from.0.max(to.0);
-        let y2 = from.1.max(to.1);
+    /// Shrinks `rect` to `ratio` (width / height), keeping the side or corner
+    /// opposite to the dragged `zone` in place.
+    fn constrain_ratio(rect: Rect, zone: &SelectionHitZone, ratio: f64) -> Rect {
+        let mut constrained = rect;
 
-        Rect {x: x1, y: y1, w: x2 - x1, h: y2 - y1 }
-    }
+        match zone {
+            SelectionHitZone::N | SelectionHitZone::S => {
+                constrained.w = ((rect.h as f64 * ratio).round() as i32).max(1);
+                constrained.x = rect.x + (rect.w - constrained.w) / 2;
+            }
 
-}
+            SelectionHitZone::E | SelectionHitZone::W => {
+                constrained.h = ((rect.w as f64 / ratio).round() as i32).max(1);
+                constrained.y = rect.y + (rect.h - constrained.h) / 2;
+            }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Selection {
+            SelectionHitZone::Inside | SelectionHitZone::Outside => {}
+
+            corner => {
+                if rect.w as f64 > rect.h as f64 * ratio {
+                    constrained.w = (rect.h as f64 * ratio).round() as i32;
+                } else {
+                    constrained.h = (rect.w as f64 / ratio).round() as i32;
+                }
+
+                if matches!(corner, SelectionHitZone::NW | SelectionHitZone::SW) {
+                    constrained.x = rect.x + rect.w - constrained.w;
+                }
+                if matches!(corner, SelectionHitZone::NW | SelectionHitZone::NE) {
+                    constrained.y = rect.y + rect.h - constrained.h;
+                }
+            }
+        }
+
+        constrained
+    }
+
+    /// Corner of the rect spanned by `from` and `to` that `to` is at.
+    fn corner_towards(from: (i32, i32), to: (i32, i32)) -> SelectionHitZone {
+        match (to.0 < from.0, to.1 < from.1) {
+            (true, true) => SelectionHitZone::NW,
+            (false, true) => SelectionHitZone::NE,
+            (true, false) => SelectionHitZone::SW,
+            (false, false) => SelectionHitZone::SE,
+        }
+    }
+
+    /// Puts the zoomed copy of `source` beside it, on the first side where it
+    /// fits in `area`, or where most of it does.
+    fn place_magnified(source: &Rect, zoom: i32, area: &Rect) -> Rect {
+        const GAP: i32 = 24;
+
+        let (w, h) = (source.w * zoom, source.h * zoom);
+        let (cx, cy) = (source.x + source.w / 2, source.y + source.h / 2);
+
+        let candidates = [
+            Rect { x: source.x + source.w + GAP, y: cy - h / 2, w, h },
+            Rect { x: source.x - GAP - w, y: cy - h / 2, w, h },
+            Rect { x: cx - w / 2, y: source.y + source.h + GAP, w, h },
+            Rect { x: cx - w / 2, y: source.y - GAP - h, w, h },
+        ];
+
+        let visible = |rect: &Rect| rect.intersection(area).map_or(0, |r| r.w as i64 * r.h as i64);
+        let best = candidates
+            .iter()
+            .find(|rect| visible(rect) == w as i64 * h as i64)
+            .or_else(|| candidates.iter().max_by_key(|rect| visible(rect)))
+            .copied()
+            .unwrap_or(candidates[0]);
+
+        // Slide along the side it was placed on to stay inside the area where possible
+        Rect {
+            x: best.x.clamp(area.x, (area.x + area.w - w).max(area.x)),
+            y: best.y.clamp(area.y, (area.y + area.h - h).max(area.y)),
+            ..best
+        }
+    }
+
+    fn rect_from_points(from: (i32, i32), to: (i32, i32)) -> Rect {
+        let x1 = from.0.min(to.0);
+        let y1 = from.1.min(to.1);
+        let x2 = from.0.max(to.0);
+        let y2 = from.1.max(to.1);
+
+        Rect {x: x1, y: y1, w: x2 - x1, h: y2 - y1 }
+    }
+
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
     rect: Rect,
     pub phase: SelectionPhase,
 }
@@ -319,14 +1186,55 @@ impl Rect {
         (self.x as f64, self.y as f64, self.w as f64, self.h as f64)
     }
 
+    pub fn expand(&self, margin: i32) -> Rect {
+        Rect {
+            x: self.x - margin,
+            y: self.y - margin,
+            w: self.w + margin * 2,
+            h: self.h + margin * 2,
+        }
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x1 = self.x.min(other.x);
+        let y1 = self.y.min(other.y);
+        let x2 = (self.x + self.w).max(other.x + other.w);
+        let y2 = (self.y + self.h).max(other.y + other.h);
+
+        Rect { x: x1, y: y1, w: x2 - x1, h: y2 - y1 }
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x1 = self.x.max(other.x);
+        let y1 = self.y.max(other.y);
+        let x2 = (self.x + self.w).min(other.x + other.w);
+        let y2 = (self.y + self.h).min(other.y + other.h);
+
+        (x2 > x1 && y2 > y1).then(|| Rect { x: x1, y: y1, w: x2 - x1, h: y2 - y1 })
+    }
+
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tool {
     None,
+    /// Picks placed annotations to move, resize, restyle or delete.
+    Select,
     Arrow,
     Rectangle,
     Blur,
+    Text,
+    Pen,
+    Highlighter,
+    Line,
+    Ellipse,
+    FilledRectangle,
+    FilledEllipse,
+    Marker,
+    Pixelate,
+    Redact,
+    Spotlight,
+    Magnify,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -338,41 +1246,938 @@ pub enum SelectionHitZone {
     NW, NE, SW, SE,
 }
 
+impl SelectionHitZone {
+    /// Which edges the zone moves, as (west, east, north, south).
+    pub fn edges(&self) -> (bool, bool, bool, bool) {
+        match self {
+            SelectionHitZone::N => (false, false, true, false),
+            SelectionHitZone::S => (false, false, false, true),
+            SelectionHitZone::E => (false, true, false, false),
+            SelectionHitZone::W => (true, false, false, false),
+            SelectionHitZone::NW => (true, false, true, false),
+            SelectionHitZone::NE => (false, true, true, false),
+            SelectionHitZone::SW => (true, false, false, true),
+            SelectionHitZone::SE => (false, true, false, true),
+            SelectionHitZone::Inside | SelectionHitZone::Outside => (false, false, false, false),
+        }
+    }
+
+    /// Mirrors the zone horizontally and/or vertically.
+    pub fn flipped(&self, horizontal: bool, vertical: bool) -> Self {
+        let (mut west, mut east, mut north, mut south) = self.edges();
+
+        if horizontal { std::mem::swap(&mut west, &mut east); }
+        if vertical { std::mem::swap(&mut north, &mut south); }
+
+        match (west, east, north, south) {
+            (false, false, true, false) => SelectionHitZone::N,
+            (false, false, false, true) => SelectionHitZone::S,
+            (false, true, false, false) => SelectionHitZone::E,
+            (true, false, false, false) => SelectionHitZone::W,
+            (true, false, true, false) => SelectionHitZone::NW,
+            (false, true, true, false) => SelectionHitZone::NE,
+            (true, false, false, true) => SelectionHitZone::SW,
+            (false, true, false, true) => SelectionHitZone::SE,
+            _ => *self,
+        }
+    }
+}
+
+/// A selected area. Freeform outlines are exported with everything outside them transparent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Rect(Rect),
+    /// Closed outline in absolute coordinates, from lasso or polygon selection.
+    Outline(Vec<(f64, f64)>),
+}
+
+impl Region {
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Region::Rect(rect) => *rect,
+            Region::Outline(points) => {
+                let x1 = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min).floor() as i32;
+                let y1 = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min).floor() as i32;
+                let x2 = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).ceil() as i32;
+                let y2 = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).ceil() as i32;
+
+                Rect { x: x1, y: y1, w: (x2 - x1).max(1), h: (y2 - y1).max(1) }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Rectangle,
+    Lasso,
+    Polygon,
+}
+
+/// Interior of a filled rectangle or ellipse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub color: (u8, u8, u8),
+    /// Percent, 0 to 100.
+    pub opacity: u8,
+}
+
+fn distance_to_segment(point: (i32, i32), from: (i32, i32), to: (i32, i32)) -> f64 {
+    let (px, py) = (point.0 as f64, point.1 as f64);
+    let (ax, ay) = (from.0 as f64, from.1 as f64);
+    let (bx, by) = (to.0 as f64, to.1 as f64);
+
+    let length = (bx - ax).powi(2) + (by - ay).powi(2);
+    let t = if length == 0.0 { 0.0 } else { (((px - ax) * (bx - ax) + (py - ay) * (by - ay)) / length).clamp(0.0, 1.0) };
+
+    (px - ax - t * (bx - ax)).hypot(py - ay - t * (by - ay))
+}
+
+/// How an annotation is drawn. Pixel effects such as blur and redaction have no style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Line width in pixels. Highlighters are drawn several times wider.
+    pub width: u8,
+    pub dash: Dash,
+    /// Percent, 0 to 100.
+    pub opacity: u8,
+    pub contrast: Contrast,
+    /// Only used by arrows.
+    pub arrowhead: Arrowhead,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: 3,
+            dash: Dash::Solid,
+            opacity: 100,
+            contrast: Contrast::None,
+            arrowhead: Arrowhead::Filled,
+        }
+    }
+}
+
+impl Style {
+    /// Room taken outside a shape's geometry by half the line width and the outline or shadow.
+    pub fn margin(&self) -> i32 {
+        let contrast = match self.contrast {
+            Contrast::None => 0.0,
+            Contrast::Outline => render::OUTLINE_GROW / 2.0,
+            Contrast::Shadow => render::SHADOW_OFFSET,
+        };
+        self.width as i32 / 2 + 2 + contrast.ceil() as i32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dash {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl Dash {
+    pub const ALL: [Dash; 3] = [Dash::Solid, Dash::Dashed, Dash::Dotted];
+}
+
+/// Keeps an annotation readable on busy or similarly coloured backgrounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contrast {
+    None,
+    /// A black or white halo, whichever differs more from the colour.
+    Outline,
+    Shadow,
+}
+
+impl Contrast {
+    pub const ALL: [Contrast; 3] = [Contrast::None, Contrast::Outline, Contrast::Shadow];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrowhead {
+    Filled,
+    Open,
+    /// Filled heads at both ends.
+    Double,
+    None,
+}
+
+impl Arrowhead {
+    pub const ALL: [Arrowhead; 4] = [Arrowhead::Filled, Arrowhead::Open, Arrowhead::Double, Arrowhead::None];
+}
+
+/// Fixed aspect ratios and sizes offered by the toolbar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionPreset {
+    Free,
+    Ratio(u32, u32),
+    Size(i32, i32),
+}
+
+/// Modifier keys held during a drag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+/// What the overlay is doing. Quick mode stays in `Selecting` and exports as soon
+/// as a selection is finished; the editor switches between `Adjusting` and
+/// `Annotating` with the tool, and Escape goes back to `Selecting`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayMode {
+    /// Drawing a selection, exported when the drag ends.
+    Selecting,
+    /// Editor: the selection can be moved, resized, typed in or extended.
+    Adjusting,
+    /// Editor with an annotation tool picked; drags draw shapes.
+    Annotating,
+    /// The result is being written out; input is ignored.
+    Exporting,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DragMode {
     Create,
     Move,
     Resize(SelectionHitZone),
+    /// Drawing an annotation with the current tool.
+    Draw,
+    /// Moving a placed annotation with the select tool.
+    MoveShape,
+    ResizeShape(SelectionHitZone),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A placed annotation taken out of the list while it is dragged around or
+/// its text is edited.
+#[derive(Debug, Clone)]
+struct MovingShape {
+    index: usize,
+    original: Shape,
+}
+
+/// A drag in progress: where it started, the selection (or dragged
+/// annotation's frame) at that moment and what it does.
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    start: (i32, i32),
+    origin: Rect,
+    mode: DragMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Shape {
     Arrow {
         from: (i32, i32),
         to: (i32, i32),
-        color: (u8, u8, u8)
+        color: (u8, u8, u8),
+        style: Style,
     },
     
     Rectangle {
         rect: Rect,
-        color: (u8, u8, u8)
+        color: (u8, u8, u8),
+        fill: Option<Fill>,
+        style: Style,
+    },
+
+    Ellipse {
+        rect: Rect,
+        color: (u8, u8, u8),
+        fill: Option<Fill>,
+        style: Style,
+    },
+
+    Line {
+        from: (i32, i32),
+        to: (i32, i32),
+        color: (u8, u8, u8),
+        style: Style,
+    },
+
+    /// Numbered circle marking a step.
+    Marker {
+        center: (i32, i32),
+        number: u32,
+        color: (u8, u8, u8),
+        style: Style,
     },
     
     Blur {
         rect: Rect,
     },
+
+    /// Replaces the area with flat blocks of its average colours.
+    Pixelate {
+        rect: Rect,
+        block: i32,
+    },
+
+    /// Covers the area with an opaque box.
+    Redact {
+        rect: Rect,
+    },
+
+    /// Enlarged copy of `source` drawn at `target`, with a border and
+    /// optionally a line back to the source.
+    Magnify {
+        source: Rect,
+        target: Rect,
+        color: (u8, u8, u8),
+        connector: bool,
+        style: Style,
+    },
+
+    /// Area left bright while the rest of the selection is dimmed on export.
+    /// Not drawn with the other shapes, see [`Spotlight`].
+    Spotlight {
+        rect: Rect,
+        ellipse: bool,
+    },
+
+    /// Multi-line text with its top-left corner at `pos`.
+    Text {
+        pos: (i32, i32),
+        text: String,
+        /// Pango font description, e.g. `Sans Bold 24`.
+        font: String,
+        color: (u8, u8, u8),
+        /// Draws a contrasting box behind the text.
+        background: bool,
+        style: Style,
+        /// Pixel size of the laid out text, measured by [`render::measure_text`]
+        /// while it is typed, so bounds don't need Pango.
+        size: (i32, i32),
+    },
+
+    /// Freehand stroke through `points`, drawn as a smooth curve.
+    Stroke {
+        points: Vec<(i32, i32)>,
+        color: (u8, u8, u8),
+        /// Wide translucent marker that multiplies with the pixels below.
+        highlighter: bool,
+        style: Style,
+    },
+}
+
+/// A placed spotlight. All of them are combined into one dimming layer over the selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spotlight {
+    pub rect: Rect,
+    pub ellipse: bool,
 }
 
 impl Shape {
+    /// Area the shape may touch when drawn, including stroke and arrowhead.
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Shape::Arrow { from, to, style, .. } => {
+                let head = render::arrowhead_length(style) as i32;
+                ScreenshotState::rect_from_points(*from, *to).expand(head + style.margin())
+            }
+            Shape::Rectangle { rect, style, .. } | Shape::Ellipse { rect, style, .. } => rect.expand(style.margin()),
+            Shape::Spotlight { rect, .. } => *rect,
+            Shape::Magnify { source, target, style, .. } => source.union(target).expand(style.margin() + 1),
+            Shape::Line { from, to, style, .. } => ScreenshotState::rect_from_points(*from, *to).expand(style.margin()),
+            Shape::Marker { center, style, .. } => {
+                let radius = render::MARKER_RADIUS as i32 + 1 + style.margin();
+                Rect { x: center.0 - radius, y: center.1 - radius, w: radius * 2, h: radius * 2 }
+            }
+            Shape::Blur { rect } | Shape::Pixelate { rect, .. } | Shape::Redact { rect } => *rect,
+            Shape::Text { pos, size, style, .. } => render::text_rect(*pos, *size).expand(style.margin() + 1),
+            Shape::Stroke { points, highlighter, style, .. } => {
+                let (x1, y1) = points.iter().fold((i32::MAX, i32::MAX), |acc, p| (acc.0.min(p.0), acc.1.min(p.1)));
+                let (x2, y2) = points.iter().fold((i32::MIN, i32::MIN), |acc, p| (acc.0.max(p.0), acc.1.max(p.1)));
+
+                // Half the stroke width plus room for the curve overshooting its points
+                let margin = render::stroke_width(style, *highlighter) as i32 / 2 + 16 + style.margin();
+                ScreenshotState::rect_from_points((x1, y1), (x2, y2)).expand(margin)
+            }
+        }
+    }
+
+    /// Box the select tool shows handles on. Resizing maps this box onto the new one.
+    pub fn frame(&self) -> Rect {
+        match self {
+            Shape::Arrow { from, to, .. } | Shape::Line { from, to, .. } => {
+                ScreenshotState::rect_from_points(*from, *to)
+            }
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Blur { rect }
+            | Shape::Pixelate { rect, .. }
+            | Shape::Redact { rect }
+            | Shape::Spotlight { rect, .. } => *rect,
+            Shape::Magnify { target, .. } => *target,
+            Shape::Marker { center, .. } => {
+                let radius = render::MARKER_RADIUS as i32;
+                Rect { x: center.0 - radius, y: center.1 - radius, w: radius * 2, h: radius * 2 }
+            }
+            Shape::Text { pos, size, .. } => render::text_rect(*pos, *size),
+            Shape::Stroke { points, .. } => {
+                let (x1, y1) = points.iter().fold((i32::MAX, i32::MAX), |acc, p| (acc.0.min(p.0), acc.1.min(p.1)));
+                let (x2, y2) = points.iter().fold((i32::MIN, i32::MIN), |acc, p| (acc.0.max(p.0), acc.1.max(p.1)));
+                ScreenshotState::rect_from_points((x1, y1), (x2, y2))
+            }
+        }
+    }
+
+    /// Markers and text keep their size; everything else can be resized.
+    pub fn is_resizable(&self) -> bool {
+        !matches!(self, Shape::Marker { .. } | Shape::Text { .. })
+    }
+
+    /// Whether a click at `point` picks this shape. Lines only count near the stroke.
+    pub fn hit(&self, point: (i32, i32)) -> bool {
+        let near = |from: (i32, i32), to: (i32, i32), style: &Style| {
+            distance_to_segment(point, from, to) <= style.width as f64 / 2.0 + 4.0
+        };
+
+        match self {
+            Shape::Arrow { from, to, style, .. } | Shape::Line { from, to, style, .. } => near(*from, *to, style),
+            Shape::Stroke { points, style, .. } => points.windows(2).any(|pair| near(pair[0], pair[1], style)),
+            Shape::Magnify { source, target, .. } => source.contains(point) || target.contains(point),
+            _ => self.frame().expand(2).contains(point),
+        }
+    }
+
+    /// Moves the shape. A magnified copy moves without its source.
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        let offset = |(x, y): (i32, i32)| (x + dx, y + dy);
+        let offset_rect = |rect: &mut Rect| {
+            rect.x += dx;
+            rect.y += dy;
+        };
+
+        match self {
+            Shape::Arrow { from, to, .. } | Shape::Line { from, to, .. } => {
+                *from = offset(*from);
+                *to = offset(*to);
+            }
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Blur { rect }
+            | Shape::Pixelate { rect, .. }
+            | Shape::Redact { rect }
+            | Shape::Spotlight { rect, .. } => offset_rect(rect),
+            Shape::Magnify { target, .. } => offset_rect(target),
+            Shape::Marker { center, .. } => *center = offset(*center),
+            Shape::Text { pos, .. } => *pos = offset(*pos),
+            Shape::Stroke { points, .. } => points.iter_mut().for_each(|point| *point = offset(*point)),
+        }
+    }
+
+    /// Stretches the shape so that the box `from` covers `to`.
+    pub fn fit(&mut self, from: &Rect, to: &Rect) {
+        let scale = |value: i32, start: i32, len: i32, new_start: i32, new_len: i32| {
+            if len == 0 {
+                new_start + value - start
+            } else {
+                new_start + ((value - start) as f64 * new_len as f64 / len as f64).round() as i32
+            }
+        };
+        let map = |(x, y): (i32, i32)| (
+            scale(x, from.x, from.w, to.x, to.w),
+            scale(y, from.y, from.h, to.y, to.h),
+        );
+
+        match self {
+            Shape::Arrow { from: start, to: end, .. } | Shape::Line { from: start, to: end, .. } => {
+                *start = map(*start);
+                *end = map(*end);
+            }
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Blur { rect }
+            | Shape::Pixelate { rect, .. }
+            | Shape::Redact { rect }
+            | Shape::Spotlight { rect, .. }
+            | Shape::Magnify { target: rect, .. } => {
+                let (x1, y1) = map((rect.x, rect.y));
+                let (x2, y2) = map((rect.x + rect.w, rect.y + rect.h));
+                *rect = ScreenshotState::rect_from_points((x1, y1), (x2, y2));
+            }
+            Shape::Stroke { points, .. } => points.iter_mut().for_each(|point| *point = map(*point)),
+            Shape::Marker { .. } | Shape::Text { .. } => {}
+        }
+    }
+
+    pub fn set_color(&mut self, new_color: (u8, u8, u8)) {
+        match self {
+            Shape::Arrow { color, .. }
+            | Shape::Rectangle { color, .. }
+            | Shape::Ellipse { color, .. }
+            | Shape::Line { color, .. }
+            | Shape::Marker { color, .. }
+            | Shape::Magnify { color, .. }
+            | Shape::Text { color, .. }
+            | Shape::Stroke { color, .. } => *color = new_color,
+            Shape::Blur { .. } | Shape::Pixelate { .. } | Shape::Redact { .. } | Shape::Spotlight { .. } => {}
+        }
+    }
+
+    /// Changes the interior of a filled rectangle or ellipse.
+    pub fn set_fill(&mut self, change: impl FnOnce(&mut Fill)) {
+        if let Shape::Rectangle { fill: Some(fill), .. } | Shape::Ellipse { fill: Some(fill), .. } = self {
+            change(fill);
+        }
+    }
+
+    pub fn style_mut(&mut self) -> Option<&mut Style> {
+        match self {
+            Shape::Arrow { style, .. }
+            | Shape::Rectangle { style, .. }
+            | Shape::Ellipse { style, .. }
+            | Shape::Line { style, .. }
+            | Shape::Marker { style, .. }
+            | Shape::Magnify { style, .. }
+            | Shape::Text { style, .. }
+            | Shape::Stroke { style, .. } => Some(style),
+            Shape::Blur { .. } | Shape::Pixelate { .. } | Shape::Redact { .. } | Shape::Spotlight { .. } => None,
+        }
+    }
+
     pub fn is_valid(&self) -> bool {
         match self {
-            Shape::Arrow { from, to, .. } => {
+            Shape::Arrow { from, to, .. } | Shape::Line { from, to, .. } => {
                 let dist = ((to.0 - from.0).pow(2) + (to.1 - from.1).pow(2)).abs();
                 dist > 10
             }
-            Shape::Rectangle { rect, .. } | Shape::Blur { rect } => {
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Blur { rect }
+            | Shape::Pixelate { rect, .. }
+            | Shape::Redact { rect }
+            | Shape::Spotlight { rect, .. } => {
                 rect.w > 5 && rect.h > 5
             }
+            Shape::Text { text, .. } => !text.trim().is_empty(),
+            Shape::Stroke { points, .. } => points.len() >= 2,
+            Shape::Marker { .. } => true,
+            Shape::Magnify { source, .. } => source.w > 3 && source.h > 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECTION: Rect = Rect { x: 100, y: 100, w: 200, h: 100 };
+
+    /// Quick mode with a finished selection.
+    fn selected() -> ScreenshotState {
+        let mut state = ScreenshotState::default();
+        state.set_selection_rect(SELECTION);
+        state
+    }
+
+    fn editing(tool: Tool) -> ScreenshotState {
+        let mut state = selected();
+        state.set_tool(tool);
+        assert!(state.enter_editor());
+        state
+    }
+
+    fn drag(state: &mut ScreenshotState, from: (i32, i32), by: (i32, i32)) -> bool {
+        state.begin_drag(from.0, from.1, Modifiers::default());
+        state.update_drag(by.0, by.1, Modifiers::default(), None);
+        state.end_drag()
+    }
+
+    #[test]
+    fn selecting_to_adjusting() {
+        let mut state = selected();
+
+        assert!(state.enter_editor());
+        assert_eq!(state.mode(), OverlayMode::Adjusting);
+    }
+
+    #[test]
+    fn selecting_to_annotating_with_tool_picked() {
+        let mut state = selected();
+        state.set_tool(Tool::Arrow);
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+
+        assert!(state.enter_editor());
+        assert_eq!(state.mode(), OverlayMode::Annotating);
+    }
+
+    #[test]
+    fn editor_needs_a_selection() {
+        let mut state = ScreenshotState::default();
+
+        assert!(!state.enter_editor());
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+    }
+
+    #[test]
+    fn set_tool_switches_between_adjusting_and_annotating() {
+        let mut state = editing(Tool::None);
+
+        state.set_tool(Tool::Rectangle);
+        assert_eq!(state.mode(), OverlayMode::Annotating);
+
+        state.set_tool(Tool::None);
+        assert_eq!(state.mode(), OverlayMode::Adjusting);
+    }
+
+    #[test]
+    fn cancel_leaves_editor_and_drops_half_drawn_shape() {
+        let mut state = editing(Tool::Arrow);
+        state.begin_drag(150, 150, Modifiers::default());
+        state.update_drag(20, 20, Modifiers::default(), None);
+        assert!(state.current_shape().is_some());
+
+        assert!(state.cancel());
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+        assert!(state.current_shape().is_none());
+        assert_eq!(*state.selection().rect(), SELECTION);
+
+        // Nothing left to back out of, so the overlay quits
+        assert!(!state.cancel());
+    }
+
+    #[test]
+    fn leave_editor_only_from_editor() {
+        let mut state = selected();
+        assert!(!state.leave_editor());
+
+        let mut state = editing(Tool::None);
+        assert!(state.leave_editor());
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+    }
+
+    #[test]
+    fn cancel_abandons_pending_polygon() {
+        let mut state = ScreenshotState::default();
+        state.set_selection_mode(SelectionMode::Polygon);
+        state.begin_drag(10, 10, Modifiers::default());
+        state.end_drag();
+        state.begin_drag(100, 10, Modifiers::default());
+        state.end_drag();
+        assert_eq!(state.pending_polygon().len(), 2);
+
+        assert!(state.cancel());
+        assert!(state.pending_polygon().is_empty());
+        assert!(!state.selection().is_active());
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+    }
+
+    #[test]
+    fn any_mode_to_exporting() {
+        for mut state in [selected(), editing(Tool::None), editing(Tool::Arrow)] {
+            assert!(state.begin_export());
+            assert_eq!(state.mode(), OverlayMode::Exporting);
+
+            // Already exporting
+            assert!(!state.begin_export());
+        }
+    }
+
+    #[test]
+    fn export_needs_a_selection() {
+        let mut state = ScreenshotState::default();
+
+        assert!(!state.begin_export());
+        assert_eq!(state.mode(), OverlayMode::Selecting);
+    }
+
+    #[test]
+    fn quick_capture_finishes_on_created_selection() {
+        let mut state = ScreenshotState::default();
+
+        assert!(drag(&mut state, (10, 10), (50, 40)));
+        assert!(state.is_quick_capture_done());
+    }
+
+    #[test]
+    fn click_after_leaving_editor_does_not_export() {
+        let mut state = editing(Tool::None);
+        assert!(state.leave_editor());
+
+        assert!(!drag(&mut state, (150, 150), (0, 0)));
+        assert!(drag(&mut state, (150, 150), (10, 0)));
+        assert!(state.is_quick_capture_done());
+    }
+
+    fn editing_text(state: &ScreenshotState) -> &str {
+        match state.editing_text() {
+            Some(Shape::Text { text, .. }) => text,
+            _ => panic!("No text box open"),
+        }
+    }
+
+    #[test]
+    fn text_caret_moves_and_deletes_by_character() {
+        let mut state = editing(Tool::Text);
+        state.begin_drag(150, 150, Modifiers::default());
+
+        state.insert_text("héllo");
+        state.move_text_caret(false);
+        state.move_text_caret(false);
+        state.move_text_caret(false);
+        state.delete_text_backward();
+        assert_eq!(editing_text(&state), "hllo");
+
+        state.insert_text("e");
+        state.delete_text_forward();
+        assert_eq!(editing_text(&state), "helo");
+
+        state.move_text_caret(true);
+        state.move_text_caret(true);
+        state.move_text_caret(true);
+        state.insert_text("!");
+        assert_eq!(editing_text(&state), "helo!");
+    }
+
+    const SCREEN: Rect = Rect { x: 0, y: 0, w: 400, h: 300 };
+
+    #[test]
+    fn nudge_is_ignored_while_annotating() {
+        let mut state = editing(Tool::Arrow);
+
+        state.nudge(5, 5, &SCREEN);
+        assert_eq!(*state.selection().rect(), SELECTION);
+    }
+
+    #[test]
+    fn nudge_stays_on_screen() {
+        let mut state = editing(Tool::None);
+
+        state.nudge(-500, 500, &SCREEN);
+        assert_eq!(*state.selection().rect(), Rect { x: 0, y: 200, ..SELECTION });
+
+        state.nudge_handle(SelectionHitZone::E, 500, 0, &SCREEN);
+        assert_eq!(*state.selection().rect(), Rect { x: 0, y: 200, w: 400, h: 100 });
+    }
+
+    #[test]
+    fn ctrl_adds_a_region_and_alt_does_not() {
+        let alt = Modifiers { alt: true, ..Modifiers::default() };
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+
+        let mut state = editing(Tool::None);
+        state.begin_drag(350, 250, alt);
+        state.update_drag(20, 20, alt, None);
+        state.end_drag();
+        assert_eq!(state.regions().len(), 1);
+
+        state.begin_drag(10, 10, ctrl);
+        state.update_drag(20, 20, ctrl, None);
+        state.end_drag();
+        assert_eq!(state.regions().len(), 2);
+    }
+
+    #[test]
+    fn resize_flips_across_the_opposite_edge() {
+        let (rect, zone) = ScreenshotState::resize_rect(&SELECTION, &SelectionHitZone::E, -250, 0);
+        assert_eq!(rect, Rect { x: 50, y: 100, w: 50, h: 100 });
+        assert_eq!(zone, SelectionHitZone::W);
+
+        let (rect, zone) = ScreenshotState::resize_rect(&SELECTION, &SelectionHitZone::NW, 300, 150);
+        assert_eq!(rect, Rect { x: 300, y: 200, w: 100, h: 50 });
+        assert_eq!(zone, SelectionHitZone::SE);
+    }
+
+    #[test]
+    fn resize_onto_the_opposite_edge_keeps_one_pixel() {
+        let (rect, zone) = ScreenshotState::resize_rect(&SELECTION, &SelectionHitZone::N, 0, 100);
+
+        assert_eq!(rect, Rect { x: 100, y: 200, w: 200, h: 1 });
+        assert_eq!(zone, SelectionHitZone::N);
+    }
+
+    #[test]
+    fn ratio_shrinks_from_the_dragged_corner() {
+        let rect = Rect { x: 0, y: 0, w: 200, h: 100 };
+
+        assert_eq!(ScreenshotState::constrain_ratio(rect, &SelectionHitZone::SE, 1.0), Rect { x: 0, y: 0, w: 100, h: 100 });
+        assert_eq!(ScreenshotState::constrain_ratio(rect, &SelectionHitZone::NW, 1.0), Rect { x: 100, y: 0, w: 100, h: 100 });
+
+        let tall = Rect { x: 0, y: 0, w: 100, h: 200 };
+        assert_eq!(ScreenshotState::constrain_ratio(tall, &SelectionHitZone::NE, 1.0), Rect { x: 0, y: 100, w: 100, h: 100 });
+    }
+
+    #[test]
+    fn ratio_from_an_edge_stays_centred() {
+        let ratio = 16.0 / 9.0;
+
+        assert_eq!(
+            ScreenshotState::constrain_ratio(Rect { x: 0, y: 0, w: 100, h: 90 }, &SelectionHitZone::N, ratio),
+            Rect { x: -30, y: 0, w: 160, h: 90 },
+        );
+        assert_eq!(
+            ScreenshotState::constrain_ratio(Rect { x: 0, y: 0, w: 160, h: 100 }, &SelectionHitZone::E, ratio),
+            Rect { x: 0, y: 5, w: 160, h: 90 },
+        );
+    }
+
+    #[test]
+    fn outline_is_relative_to_its_bounding_box() {
+        let (rect, outline) = ScreenshotState::normalize_outline(&[(10, 20), (110, 20), (60, 70)]);
+
+        assert_eq!(rect, Rect { x: 10, y: 20, w: 100, h: 50 });
+        assert_eq!(outline, vec![(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)]);
+    }
+
+    #[test]
+    fn degenerate_outline_keeps_a_pixel() {
+        let (rect, outline) = ScreenshotState::normalize_outline(&[(5, 5), (5, 5)]);
+
+        assert_eq!(rect, Rect { x: 5, y: 5, w: 1, h: 1 });
+        assert_eq!(outline, vec![(0.0, 0.0), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn magnified_copy_goes_to_the_first_side_that_fits() {
+        let area = Rect { x: 0, y: 0, w: 1000, h: 1000 };
+
+        let right = ScreenshotState::place_magnified(&Rect { x: 100, y: 100, w: 20, h: 20 }, 3, &area);
+        assert_eq!(right, Rect { x: 144, y: 80, w: 60, h: 60 });
+
+        let left = ScreenshotState::place_magnified(&Rect { x: 950, y: 100, w: 20, h: 20 }, 3, &area);
+        assert_eq!(left, Rect { x: 866, y: 80, w: 60, h: 60 });
+    }
+
+    #[test]
+    fn magnified_copy_slides_into_a_tight_area() {
+        let area = Rect { x: 0, y: 0, w: 200, h: 200 };
+        let placed = ScreenshotState::place_magnified(&Rect { x: 170, y: 0, w: 20, h: 20 }, 3, &area);
+
+        assert_eq!(placed, Rect { x: 140, y: 44, w: 60, h: 60 });
+    }
+
+    #[test]
+    fn export_keeps_shape_being_moved() {
+        let mut state = editing(Tool::Rectangle);
+        drag(&mut state, (120, 120), (40, 40));
+        state.set_tool(Tool::Select);
+
+        state.begin_drag(120, 140, Modifiers::default());
+        state.update_drag(30, 0, Modifiers::default(), None);
+        assert!(state.shapes().is_empty());
+
+        assert!(state.begin_export());
+        assert_eq!(state.shapes().len(), 1);
+        assert_eq!(state.shapes()[0].frame().x, 150);
+        assert!(state.current_shape().is_none());
+    }
+
+    #[test]
+    fn export_places_shape_being_drawn_and_text_being_typed() {
+        let mut state = editing(Tool::Arrow);
+        state.begin_drag(150, 150, Modifiers::default());
+        state.update_drag(20, 20, Modifiers::default(), None);
+
+        assert!(state.begin_export());
+        assert!(matches!(state.shapes(), [Shape::Arrow { .. }]));
+
+        let mut state = editing(Tool::Text);
+        state.begin_drag(150, 150, Modifiers::default());
+        state.insert_text("hi");
+
+        assert!(state.begin_export());
+        assert!(matches!(state.shapes(), [Shape::Text { .. }]));
+        assert!(state.editing_text().is_none());
+    }
+
+    #[test]
+    fn leaving_editor_keeps_shape_being_moved() {
+        let mut state = editing(Tool::Rectangle);
+        drag(&mut state, (120, 120), (40, 40));
+        state.set_tool(Tool::Select);
+
+        state.begin_drag(120, 140, Modifiers::default());
+        state.update_drag(0, 30, Modifiers::default(), None);
+
+        assert!(state.leave_editor());
+        assert_eq!(state.shapes().len(), 1);
+        assert_eq!(state.shapes()[0].frame().y, 150);
+    }
+
+    #[test]
+    fn typed_rect_replaces_freeform_and_extra_regions() {
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        let mut state = editing(Tool::None);
+
+        state.set_selection_mode(SelectionMode::Lasso);
+        state.begin_drag(10, 10, ctrl);
+        for (dx, dy) in [(40, 0), (40, 40), (0, 40)] {
+            state.update_drag(dx, dy, ctrl, None);
         }
+        state.end_drag();
+        assert!(matches!(state.regions().as_slice(), [Region::Outline(_), Region::Rect(_)]));
+
+        let typed = Rect { x: 500, y: 400, w: 300, h: 200 };
+        state.set_selection_rect(typed);
+
+        assert_eq!(state.regions(), [Region::Rect(typed)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn size_preset_stays_on_screen() {
+        let mut state = ScreenshotState::default();
+        state.set_selection_rect(Rect { x: 300, y: 250, w: 50, h: 20 });
+
+        state.apply_preset(SelectionPreset::Size(200, 100), &SCREEN);
+        assert_eq!(*state.selection().rect(), Rect { x: 200, y: 200, w: 200, h: 100 });
+
+        // Larger than the capture
+        state.apply_preset(SelectionPreset::Size(1920, 1080), &SCREEN);
+        assert_eq!(*state.selection().rect(), SCREEN);
+    }
+
+    /// Places a text box reading `text` at (150, 150), measured as 50x20.
+    fn place_text(state: &mut ScreenshotState, text: &str) {
+        state.begin_drag(150, 150, Modifiers::default());
+        state.insert_text(text);
+        state.set_text_size((50, 20));
+        assert!(state.finish_text());
+    }
+
+    #[test]
+    fn clicking_placed_text_reopens_it() {
+        let mut state = editing(Tool::Text);
+        place_text(&mut state, "hello");
+
+        state.begin_drag(170, 160, Modifiers::default());
+        assert!(state.shapes().is_empty());
+        assert!(matches!(state.editing_text(), Some(Shape::Text { text, .. }) if text == "hello"));
+        assert_eq!(state.text_caret(), 5);
+
+        state.insert_text("!");
+        assert!(state.finish_text());
+        assert!(matches!(state.shapes(), [Shape::Text { text, .. }] if text == "hello!"));
+
+        state.undo();
+        assert!(matches!(state.shapes(), [Shape::Text { text, .. }] if text == "hello"));
+    }
+
+    #[test]
+    fn emptied_text_is_removed_undoably() {
+        let mut state = editing(Tool::Text);
+        place_text(&mut state, "hi");
+
+        state.begin_drag(160, 160, Modifiers::default());
+        state.delete_text_backward();
+        state.delete_text_backward();
+        assert!(state.finish_text());
+        assert!(state.shapes().is_empty());
+
+        state.undo();
+        assert_eq!(state.shapes().len(), 1);
+    }
+
+    #[test]
+    fn clicking_elsewhere_starts_new_text() {
+        let mut state = editing(Tool::Text);
+        place_text(&mut state, "hello");
+
+        state.begin_drag(250, 180, Modifiers::default());
+        assert_eq!(state.shapes().len(), 1);
+        assert!(matches!(state.editing_text(), Some(Shape::Text { text, .. }) if text.is_empty()));
+    }
+}
@@ -0,0 +1,83 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+use gdk::{Key, ModifierType};
+use glib::clone;
+use gtk::{ApplicationWindow, DrawingArea, EventControllerKey, IMMulticontext, PropagationPhase};
+use gtk::prelude::*;
+
+use crate::action::{AppAction, ScreenshotAction};
+use crate::modules::screenshot::state::Rect;
+
+/// Routes typing to the text annotation being edited, through the input method
+/// so compose sequences, CJK input and emoji work. Only enabled while a text box
+/// is open; it then takes key presses before the overlay shortcuts.
+#[derive(Debug, Clone)]
+pub struct TextInput {
+    controller: EventControllerKey,
+    im_context: IMMulticontext,
+    active: Rc<Cell<bool>>,
+}
+
+impl TextInput {
+    pub fn new(tx: Sender<AppAction>, window: &ApplicationWindow, area: &DrawingArea) -> Self {
+        let im_context = IMMulticontext::new();
+        im_context.set_client_widget(Some(area));
+
+        im_context.connect_commit(clone!(
+            #[strong] tx,
+            move |_, text| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::TextInput(text.to_string())));
+            }
+        ));
+
+        let controller = EventControllerKey::new();
+        controller.set_im_context(Some(&im_context));
+        controller.set_propagation_phase(PropagationPhase::None);
+
+        controller.connect_key_pressed(move |_, key, _, modifiers| {
+            let ctrl = modifiers.contains(ModifierType::CONTROL_MASK);
+
+            let action = match key {
+                Key::BackSpace => ScreenshotAction::TextBackspace,
+                Key::Delete | Key::KP_Delete => ScreenshotAction::TextDelete,
+                Key::Left | Key::KP_Left => ScreenshotAction::TextMoveCaret(false),
+                Key::Right | Key::KP_Right => ScreenshotAction::TextMoveCaret(true),
+                Key::Return | Key::KP_Enter if ctrl => ScreenshotAction::TextCommit,
+                Key::Return | Key::KP_Enter => ScreenshotAction::TextInput("\n".to_string()),
+                Key::Escape => ScreenshotAction::TextCommit,
+                // Leave Ctrl shortcuts such as Ctrl+S to the overlay
+                _ if ctrl => return glib::Propagation::Proceed,
+                _ => return glib::Propagation::Stop,
+            };
+
+            let _ = tx.send(AppAction::Screenshot(action));
+            glib::Propagation::Stop
+        });
+
+        window.add_controller(controller.clone());
+
+        Self { controller, im_context, active: Default::default() }
+    }
+
+    /// Enables or disables text entry. `caret` tells the input method where to show its popup.
+    pub fn set_active(&self, active: bool, caret: Option<Rect>) {
+        if let Some(caret) = caret {
+            self.im_context.set_cursor_location(&gdk::Rectangle::new(caret.x, caret.y, caret.w, caret.h));
+        }
+
+        if self.active.replace(active) == active {
+            return;
+        }
+
+        if active {
+            self.controller.set_propagation_phase(PropagationPhase::Capture);
+            self.im_context.focus_in();
+        } else {
+            self.im_context.reset();
+            self.im_context.focus_out();
+            self.controller.set_propagation_phase(PropagationPhase::None);
+        }
+    }
+}
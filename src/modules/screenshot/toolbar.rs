@@ -3,11 +3,14 @@ use std::rc::Rc;
 use std::sync::mpsc::Sender;
 
 use glib::clone;
-use gtk::{Box, Button, CssProvider, DrawingArea, Grid, Image, Overlay, Popover, ToggleButton};
+use gtk::{
+    Box, Button, CheckButton, CssProvider, DrawingArea, DropDown, FontButton, Grid, Image, Label,
+    Overlay, Popover, Scale, ToggleButton,
+};
 use gtk::{prelude::*};
 
 use crate::action::{AppAction, ScreenshotAction};
-use crate::modules::screenshot::state::{Rect, Tool};
+use crate::modules::screenshot::state::{Arrowhead, Contrast, Dash, Rect, SelectionPreset, Style, Tool};
 
 macro_rules! create_exlusive_toolbuttons {
     (
@@ -78,7 +81,7 @@ impl Toolbar {
             .spacing(6).focusable(false)
             .halign(gtk::Align::Start).valign(gtk::Align::Start)
             .css_name("toolbar")
-            .can_target(true)
+            .can_target(false)
             .opacity(0.0)
             .hexpand(false)
             .vexpand(false)
@@ -88,7 +91,16 @@ impl Toolbar {
 
         toolbar.setup_drawing_tools(tx.clone());
         toolbar.setup_undo_button(tx.clone());
+        toolbar.setup_redo_button(tx.clone());
+        toolbar.setup_marker_reset_button(tx.clone());
         toolbar.setup_color_picker_button(tx.clone());
+        toolbar.setup_fill_button(tx.clone());
+        toolbar.setup_style_button(tx.clone());
+        toolbar.setup_text_options_button(tx.clone());
+        toolbar.setup_pixel_block_button(tx.clone());
+        toolbar.setup_spotlight_button(tx.clone());
+        toolbar.setup_magnify_button(tx.clone());
+        toolbar.setup_preset_button(tx.clone());
 
         toolbar
     }
@@ -99,9 +111,22 @@ impl Toolbar {
             container = self.container,
             active_by_default = Tool::None,
             tools = [
+                ("/io/github/misery8/hyprshot/icons/symbolic/select-symbolic.svg", Tool::Select),
                 ("/io/github/misery8/hyprshot/icons/symbolic/diagonal-arrow-symbolic.svg", Tool::Arrow),
+                ("/io/github/misery8/hyprshot/icons/symbolic/line-symbolic.svg", Tool::Line),
                 ("/io/github/misery8/hyprshot/icons/symbolic/rectangle-symbolic.svg", Tool::Rectangle),
+                ("/io/github/misery8/hyprshot/icons/symbolic/filled-rectangle-symbolic.svg", Tool::FilledRectangle),
+                ("/io/github/misery8/hyprshot/icons/symbolic/ellipse-symbolic.svg", Tool::Ellipse),
+                ("/io/github/misery8/hyprshot/icons/symbolic/filled-ellipse-symbolic.svg", Tool::FilledEllipse),
                 ("/io/github/misery8/hyprshot/icons/symbolic/drop-water-symbolic.svg", Tool::Blur),
+                ("/io/github/misery8/hyprshot/icons/symbolic/pixelate-symbolic.svg", Tool::Pixelate),
+                ("/io/github/misery8/hyprshot/icons/symbolic/redact-symbolic.svg", Tool::Redact),
+                ("/io/github/misery8/hyprshot/icons/symbolic/spotlight-symbolic.svg", Tool::Spotlight),
+                ("/io/github/misery8/hyprshot/icons/symbolic/magnify-symbolic.svg", Tool::Magnify),
+                ("/io/github/misery8/hyprshot/icons/symbolic/text-symbolic.svg", Tool::Text),
+                ("/io/github/misery8/hyprshot/icons/symbolic/marker-symbolic.svg", Tool::Marker),
+                ("/io/github/misery8/hyprshot/icons/symbolic/pen-symbolic.svg", Tool::Pen),
+                ("/io/github/misery8/hyprshot/icons/symbolic/highlighter-symbolic.svg", Tool::Highlighter),
             ]
         };
     }
@@ -114,7 +139,68 @@ impl Toolbar {
         self.container.append(&button);
     }
 
+    fn setup_redo_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/redo-symbolic.svg");
+        button.connect_clicked(clone!(#[strong] tx, move |_| {
+            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::Redo));
+        }));
+        self.container.append(&button);
+    }
+
+    fn setup_marker_reset_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/marker-reset-symbolic.svg");
+        button.set_tooltip_text(Some("Restart step numbers at 1"));
+        button.connect_clicked(clone!(#[strong] tx, move |_| {
+            let _ = tx.send(AppAction::Screenshot(ScreenshotAction::ResetMarkers));
+        }));
+        self.container.append(&button);
+    }
+
     fn setup_color_picker_button(&self, tx: Sender<AppAction>) {
+        self.setup_palette_button(
+            tx,
+            "/io/github/misery8/hyprshot/icons/symbolic/palette-symbolic.svg",
+            ScreenshotAction::SetColor,
+            None,
+        );
+    }
+
+    /// Fill colour and opacity of the filled rectangle and ellipse tools.
+    fn setup_fill_button(&self, tx: Sender<AppAction>) {
+        let opacity = Scale::with_range(gtk::Orientation::Horizontal, 0.0, 100.0, 5.0);
+        opacity.set_value(40.0);
+        opacity.set_focusable(false);
+        opacity.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetFillOpacity(scale.value() as u8)));
+            }
+        ));
+
+        let options = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .build();
+        options.append(&Label::new(Some("Fill opacity")));
+        options.append(&opacity);
+
+        self.setup_palette_button(
+            tx,
+            "/io/github/misery8/hyprshot/icons/symbolic/fill-symbolic.svg",
+            ScreenshotAction::SetFillColor,
+            Some(options.upcast_ref()),
+        );
+    }
+
+    /// Adds a button that shows the picked colour over `icon` and opens the palette.
+    /// Picked colours are sent as `action`; `extra` is shown below the palette.
+    fn setup_palette_button(
+        &self,
+        tx: Sender<AppAction>,
+        icon: &str,
+        action: fn(u8, u8, u8) -> ScreenshotAction,
+        extra: Option<&gtk::Widget>,
+    ) {
         let current_color = Rc::new(Cell::new((255u8, 0u8, 0u8)));
 
         let color_indicator = DrawingArea::builder()
@@ -132,7 +218,7 @@ impl Toolbar {
             }
         ));
 
-        let icon = Image::from_resource("/io/github/misery8/hyprshot/icons/symbolic/palette-symbolic.svg");
+        let icon = Image::from_resource(icon);
         icon.set_size_request(24, 24);
 
         let overlay = Overlay::builder()
@@ -153,11 +239,24 @@ impl Toolbar {
 
         let grid = Self::build_color_picker_grid(
             tx,
+            action,
             current_color,
             &color_indicator,
             &popover
         );
-        popover.set_child(Some(&grid));
+
+        match extra {
+            Some(extra) => {
+                let content = Box::builder()
+                    .orientation(gtk::Orientation::Vertical)
+                    .spacing(6)
+                    .build();
+                content.append(&grid);
+                content.append(extra);
+                popover.set_child(Some(&content));
+            }
+            None => popover.set_child(Some(&grid)),
+        }
 
         button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
        
@@ -166,6 +265,7 @@ impl Toolbar {
 
     fn build_color_picker_grid(
         tx: Sender<AppAction>,
+        action: fn(u8, u8, u8) -> ScreenshotAction,
         indicator_color: Rc<Cell<(u8, u8, u8)>>,
         drawing_area: &DrawingArea,
         popover: &Popover,
@@ -205,7 +305,7 @@ impl Toolbar {
                 #[strong] drawing_area,
                 #[weak] popover,
                 move |_| {
-                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetColor(red, green, blue)));
+                let _ = tx.send(AppAction::Screenshot(action(red, green, blue)));
                 
                 indicator_color.set((red, green, blue));
                 drawing_area.queue_draw();
@@ -219,8 +319,307 @@ impl Toolbar {
         grid
     }
 
+    fn setup_preset_button(&self, tx: Sender<AppAction>) {
+        const PRESETS: &[(&str, SelectionPreset)] = &[
+            ("Free", SelectionPreset::Free),
+            ("16:9", SelectionPreset::Ratio(16, 9)),
+            ("4:3", SelectionPreset::Ratio(4, 3)),
+            ("1:1", SelectionPreset::Ratio(1, 1)),
+            ("1280×720", SelectionPreset::Size(1280, 720)),
+            ("1920×1080", SelectionPreset::Size(1920, 1080)),
+            ("1200×630", SelectionPreset::Size(1200, 630)),
+        ];
+
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/aspect-ratio-symbolic.svg");
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let list = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .build();
+
+        for &(label, preset) in PRESETS {
+            let item = Button::builder()
+                .label(label)
+                .focusable(false)
+                .css_classes(["flat"])
+                .build();
+
+            item.connect_clicked(clone!(
+                #[strong] tx,
+                #[weak] popover,
+                move |_| {
+                    let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetPreset(preset)));
+                    popover.popdown();
+                }
+            ));
+
+            list.append(&item);
+        }
+        popover.set_child(Some(&list));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
+    fn setup_pixel_block_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/pixel-size-symbolic.svg");
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let block = Scale::with_range(gtk::Orientation::Horizontal, 4.0, 48.0, 2.0);
+        block.set_value(12.0);
+        block.set_focusable(false);
+        block.set_width_request(160);
+        block.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetPixelBlock(scale.value() as i32)));
+            }
+        ));
+
+        let content = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .build();
+        content.append(&Label::new(Some("Pixel block size")));
+        content.append(&block);
+        popover.set_child(Some(&content));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
+    fn setup_spotlight_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/spotlight-options-symbolic.svg");
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let ellipse = CheckButton::with_label("Ellipse");
+        ellipse.connect_toggled(clone!(
+            #[strong] tx,
+            move |check| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetSpotlightEllipse(check.is_active())));
+            }
+        ));
+
+        let opacity = Scale::with_range(gtk::Orientation::Horizontal, 10.0, 90.0, 5.0);
+        opacity.set_value(50.0);
+        opacity.set_focusable(false);
+        opacity.set_width_request(160);
+        opacity.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetSpotlightOpacity(scale.value() as u8)));
+            }
+        ));
+
+        let content = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .build();
+        content.append(&ellipse);
+        content.append(&Label::new(Some("Dim outside")));
+        content.append(&opacity);
+        popover.set_child(Some(&content));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
+    fn setup_magnify_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/magnify-zoom-symbolic.svg");
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let zoom = Scale::with_range(gtk::Orientation::Horizontal, 2.0, 8.0, 1.0);
+        zoom.set_value(3.0);
+        zoom.set_digits(0);
+        zoom.set_draw_value(true);
+        zoom.set_focusable(false);
+        zoom.set_width_request(160);
+        zoom.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetMagnifyZoom(scale.value() as i32)));
+            }
+        ));
+
+        let connector = CheckButton::with_label("Connector line");
+        connector.set_active(true);
+        connector.connect_toggled(clone!(
+            #[strong] tx,
+            move |check| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetMagnifyConnector(check.is_active())));
+            }
+        ));
+
+        let content = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .build();
+        content.append(&Label::new(Some("Zoom")));
+        content.append(&zoom);
+        content.append(&connector);
+        popover.set_child(Some(&content));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
+    fn setup_style_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/style-symbolic.svg");
+        let defaults = Style::default();
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let width = Scale::with_range(gtk::Orientation::Horizontal, 1.0, 24.0, 1.0);
+        width.set_value(defaults.width as f64);
+        width.set_digits(0);
+        width.set_draw_value(true);
+        width.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetStrokeWidth(scale.value() as u8)));
+            }
+        ));
+
+        let opacity = Scale::with_range(gtk::Orientation::Horizontal, 10.0, 100.0, 5.0);
+        opacity.set_value(defaults.opacity as f64);
+        opacity.set_digits(0);
+        opacity.set_draw_value(true);
+        opacity.connect_value_changed(clone!(
+            #[strong] tx,
+            move |scale| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetOpacity(scale.value() as u8)));
+            }
+        ));
+
+        let dash = DropDown::from_strings(&["Solid", "Dashed", "Dotted"]);
+        dash.connect_selected_notify(clone!(
+            #[strong] tx,
+            move |dash| {
+                let value = Dash::ALL[dash.selected() as usize % Dash::ALL.len()];
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetDash(value)));
+            }
+        ));
+
+        let contrast = DropDown::from_strings(&["None", "Outline", "Shadow"]);
+        contrast.connect_selected_notify(clone!(
+            #[strong] tx,
+            move |contrast| {
+                let value = Contrast::ALL[contrast.selected() as usize % Contrast::ALL.len()];
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetContrast(value)));
+            }
+        ));
+
+        let arrowhead = DropDown::from_strings(&["Filled", "Open", "Double", "None"]);
+        arrowhead.connect_selected_notify(clone!(
+            #[strong] tx,
+            move |arrowhead| {
+                let value = Arrowhead::ALL[arrowhead.selected() as usize % Arrowhead::ALL.len()];
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetArrowhead(value)));
+            }
+        ));
+
+        let grid = Grid::builder()
+            .row_spacing(6)
+            .column_spacing(8)
+            .build();
+
+        let rows: [(&str, &gtk::Widget); 5] = [
+            ("Width", width.upcast_ref()),
+            ("Opacity", opacity.upcast_ref()),
+            ("Line", dash.upcast_ref()),
+            ("Contrast", contrast.upcast_ref()),
+            ("Arrowhead", arrowhead.upcast_ref()),
+        ];
+        for (row, (name, widget)) in rows.into_iter().enumerate() {
+            let label = Label::new(Some(name));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+        width.set_width_request(160);
+        popover.set_child(Some(&grid));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
+    fn setup_text_options_button(&self, tx: Sender<AppAction>) {
+        let button = default_button("/io/github/misery8/hyprshot/icons/symbolic/font-symbolic.svg");
+
+        let popover = Popover::builder()
+            .autohide(true)
+            .build();
+        popover.set_parent(&button);
+
+        let font_button = FontButton::builder()
+            .font("Sans 24")
+            .use_font(true)
+            .build();
+
+        font_button.connect_font_set(clone!(
+            #[strong] tx,
+            move |font_button| {
+                if let Some(font) = font_button.font() {
+                    let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetTextFont(font.to_string())));
+                }
+            }
+        ));
+
+        let background = CheckButton::with_label("Background box");
+        background.connect_toggled(clone!(
+            #[strong] tx,
+            move |check| {
+                let _ = tx.send(AppAction::Screenshot(ScreenshotAction::SetTextBackground(check.is_active())));
+            }
+        ));
+
+        let list = Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .build();
+        list.append(&font_button);
+        list.append(&background);
+        popover.set_child(Some(&list));
+
+        button.connect_clicked(clone!(#[strong] popover, move |_| popover.popup()));
+
+        self.container.append(&button);
+    }
+
     pub fn widget(&self) -> &Box { &self.container }
 
+    /// The toolbar only shows in the editor. It keeps its size while hidden so
+    /// it can be placed before it appears, but no longer takes clicks.
+    pub fn set_shown(&self, shown: bool) {
+        self.container.set_opacity(if shown { 1.0 } else { 0.0 });
+        self.container.set_can_target(shown);
+    }
+
     pub fn update_position(&self, rect: &Rect) {
         let allocation = self.container.allocation();
         let x_pos = (rect.x + rect.w - allocation.width()).max(10);
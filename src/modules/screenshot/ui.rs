@@ -7,7 +7,9 @@ use gtk4_layer_shell::LayerShell;
 use crate::action::AppAction;
 use crate::modules::screenshot::canvas::Canvas;
 use crate::modules::screenshot::render;
-use crate::modules::screenshot::state::ScreenshotState;
+use crate::modules::screenshot::selection_editor::SelectionEditor;
+use crate::modules::screenshot::state::{ScreenshotState, SelectionPhase, Tool};
+use crate::modules::screenshot::text_input::TextInput;
 use crate::modules::screenshot::toolbar::Toolbar;
 
 
@@ -15,6 +17,8 @@ pub struct ScreenshotWidgets {
     pub window: ApplicationWindow,
     pub drawing_area: DrawingArea,
     pub toolbar: Toolbar,
+    pub selection_editor: Rc<SelectionEditor>,
+    pub text_input: TextInput,
 }
 
 impl ScreenshotWidgets {
@@ -37,7 +41,8 @@ impl ScreenshotWidgets {
 
         Self::setup_render_loop(&drawing_area, state, canvas);
         
-        let toolbar = Toolbar::new(tx);
+        let selection_editor = SelectionEditor::new(tx.clone(), &drawing_area, da_size.0);
+        let toolbar = Toolbar::new(tx.clone());
         let overlay = Self::setup_layout(&drawing_area, toolbar.widget());
 
         let window = ApplicationWindow::builder()
@@ -56,9 +61,11 @@ impl ScreenshotWidgets {
         window.set_anchor(gtk4_layer_shell::Edge::Left, true);
         window.set_anchor(gtk4_layer_shell::Edge::Right, true);
 
+        let text_input = TextInput::new(tx, &window, &drawing_area);
+
         window.present();
 
-        Self { window, drawing_area, toolbar }
+        Self { window, drawing_area, toolbar, selection_editor, text_input }
     }
 
     fn setup_layout(da: &DrawingArea, toolbar_widget: &gtk::Box) -> Overlay {
@@ -77,22 +84,57 @@ impl ScreenshotWidgets {
     ) {
         da.set_draw_func(move |area, cr, _, _| {
             let state = state.borrow();
-            let surface = canvas.surface.borrow();
-            
-            cr.set_source_surface(&*surface, 0.0, 0.0).unwrap();
-            cr.paint().unwrap();
+            let composed = canvas.composed(state.shapes(), state.shapes_revision())
+                .unwrap_or_else(|err| {
+                    eprintln!("hyprshot: failed to draw annotations: {err:#}");
+                    canvas.surface.borrow()
+                });
+            let sampled = canvas.sampled();
+
+            if let Err(err) = composed.paint(cr, 0.0, 0.0) {
+                eprintln!("hyprshot: failed to paint capture: {err:#}");
+            }
+
+            let regions = state.regions();
+            let spotlights = state.spotlights();
+            for region in &regions {
+                render::draw_spotlight(cr, region, &spotlights, state.spotlight_opacity());
+            }
+
+            render::draw_dim(cr, (area.width() as f64, area.height() as f64), &regions);
+
+            for region in &regions {
+                render::draw_selection(cr, region);
+            }
 
-            cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
-            cr.rectangle(0.0, 0.0, area.width() as f64, area.height() as f64);
+            render::draw_polygon_preview(cr, state.pending_polygon(), state.mouse_pos());
 
-            if state.selection().is_active() {            
-                render::draw_selection(cr, state.selection().rect());
+            if state.selection().phase == SelectionPhase::Finalized
+                && state.current_tool() == Tool::None
+            {
+                render::draw_handles(cr, state.selection().rect(), state.active_handle());
             }
 
-            cr.fill().unwrap();
+            if state.selection().is_active() && !state.selection().rect().is_empty() {
+                render::draw_selection_label(cr, state.selection().rect());
+            }
 
             if let Some(shape) = state.current_shape() {
-                render::draw_shape(&surface, cr, shape);
+                render::draw_shape(&sampled, cr, shape);
+            }
+
+            if let Some((frame, resizable)) = state.selected_frame() {
+                render::draw_shape_frame(cr, &frame, resizable);
+            }
+
+            if let Some(text) = state.editing_text() {
+                render::draw_shape(&sampled, cr, text);
+                render::draw_text_editing(cr, text, state.text_caret());
+            }
+
+            if state.shows_loupe() {
+                let bounds = (area.width() as f64, area.height() as f64);
+                render::draw_loupe(cr, &composed, state.mouse_pos(), bounds);
             }
         });
     }